@@ -0,0 +1,43 @@
+//! XDG base directory lookup
+
+use std::path::PathBuf;
+
+/// `$XDG_DATA_HOME` followed by `$XDG_DATA_DIRS`, most important first
+pub fn data_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = dirs::data_dir().into_iter().collect();
+    dirs.extend(system_data_dirs());
+    dirs
+}
+
+/// `$XDG_DATA_DIRS`, most important first
+pub fn system_data_dirs() -> Vec<PathBuf> {
+    split_env("XDG_DATA_DIRS", "/usr/local/share:/usr/share")
+}
+
+/// `$XDG_CONFIG_HOME` followed by `$XDG_CONFIG_DIRS`, most important first
+pub fn config_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = dirs::config_dir().into_iter().collect();
+    dirs.extend(split_env("XDG_CONFIG_DIRS", "/etc/xdg"));
+    dirs
+}
+
+/// Names of the current desktop(s) from `$XDG_CURRENT_DESKTOP`, lowercased,
+/// most important first
+pub fn current_desktops() -> Vec<String> {
+    std::env::var("XDG_CURRENT_DESKTOP")
+        .unwrap_or_default()
+        .split(':')
+        .filter(|name| !name.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect()
+}
+
+/// Entries of a colon separated path list in environment variable `var`,
+/// or of `default` if it's unset or empty
+fn split_env(var: &str, default: &str) -> Vec<PathBuf> {
+    let value = std::env::var_os(var).filter(|v| !v.is_empty());
+    let value = value.as_deref().unwrap_or(default.as_ref());
+    std::env::split_paths(value)
+        .filter(|path| path.is_absolute())
+        .collect()
+}
@@ -0,0 +1,128 @@
+//! Bounds-checked reads from the binary caches of the MIME database and icon themes
+//!
+//! Both are big endian, with NUL terminated strings and lists referred to by their offset
+//! from the start of the file. Offsets and counts come from the file, so every read is
+//! checked and gives `None` rather than panicking on a corrupt or truncated cache.
+
+use std::path::Path;
+
+pub struct CacheFile {
+    data: Vec<u8>,
+}
+
+impl CacheFile {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self { data }
+    }
+
+    pub fn read(path: &Path) -> Option<Self> {
+        std::fs::read(path).ok().map(Self::new)
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn u16_at(&self, at: u32) -> Option<u16> {
+        Some(u16::from_be_bytes(self.bytes_at(at, 2)?.try_into().ok()?))
+    }
+
+    pub fn u32_at(&self, at: u32) -> Option<u32> {
+        Some(u32::from_be_bytes(self.bytes_at(at, 4)?.try_into().ok()?))
+    }
+
+    /// The NUL terminated string at `at`
+    pub fn str_at(&self, at: u32) -> Option<&str> {
+        let rest = self.data.get(at as usize..)?;
+        let len = rest.iter().position(|&b| b == 0)?;
+        std::str::from_utf8(&rest[..len]).ok()
+    }
+
+    pub fn bytes_at(&self, at: u32, len: usize) -> Option<&[u8]> {
+        let at = at as usize;
+        self.data.get(at..at.checked_add(len)?)
+    }
+
+    /// `count`, or fewer if the data ends before that many entries of `size` bytes at `first`,
+    /// so a corrupt count can't have us walk billions of entries
+    pub fn entries(&self, first: u32, count: u32, size: u32) -> u32 {
+        let room = self.data.len().saturating_sub(first as usize) / size as usize;
+        count.min(u32::try_from(room).unwrap_or(u32::MAX))
+    }
+}
+
+/// `base + skip + index * size`, unless that overflows
+pub fn offset(base: u32, skip: u32, index: u32, size: u32) -> Option<u32> {
+    base.checked_add(skip)?
+        .checked_add(index.checked_mul(size)?)
+}
+
+/// Call `look_up` with every truncation of the cache `data`, and with copies that have a
+/// word overwritten by values that overflow when used as offsets or counts
+#[cfg(test)]
+pub fn for_each_damaged(data: &[u8], mut look_up: impl FnMut(CacheFile)) {
+    for len in 0..data.len() {
+        look_up(CacheFile::new(data[..len].to_vec()));
+    }
+    for at in 0..data.len().saturating_sub(3) {
+        for value in [u32::MAX, u32::MAX - 3, u32::MAX / 2, 0x8000_0001] {
+            let mut damaged = data.to_vec();
+            damaged[at..at + 4].copy_from_slice(&value.to_be_bytes());
+            look_up(CacheFile::new(damaged));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file() -> CacheFile {
+        CacheFile::new(b"\x00\x01\x00\x00\x00\x02abc\x00d\xff\x00tail".to_vec())
+    }
+
+    #[test]
+    fn numbers_are_big_endian() {
+        let file = file();
+        assert_eq!(file.u16_at(0), Some(1));
+        assert_eq!(file.u32_at(2), Some(2));
+        assert_eq!(file.bytes_at(6, 3), Some(&b"abc"[..]));
+    }
+
+    #[test]
+    fn strings_end_at_nul() {
+        let file = file();
+        assert_eq!(file.str_at(6), Some("abc"));
+        assert_eq!(file.str_at(9), Some(""));
+        // Not UTF-8, and not terminated
+        assert_eq!(file.str_at(10), None);
+        assert_eq!(file.str_at(13), None);
+    }
+
+    #[test]
+    fn reads_past_the_end_fail() {
+        let file = file();
+        let len = 17;
+        assert_eq!(file.u32_at(len - 3), None);
+        assert_eq!(file.u16_at(len), None);
+        assert_eq!(file.str_at(len + 1), None);
+        assert_eq!(file.u32_at(u32::MAX), None);
+        assert_eq!(file.bytes_at(u32::MAX, usize::MAX), None);
+    }
+
+    #[test]
+    fn counts_are_capped_by_the_data() {
+        let file = file();
+        assert_eq!(file.entries(0, 2, 4), 2);
+        assert_eq!(file.entries(0, u32::MAX, 4), 17 / 4);
+        assert_eq!(file.entries(u32::MAX, 10, 4), 0);
+    }
+
+    #[test]
+    fn offsets_dont_overflow() {
+        assert_eq!(offset(100, 4, 3, 12), Some(140));
+        assert_eq!(offset(u32::MAX, 1, 0, 0), None);
+        assert_eq!(offset(0, 0, u32::MAX, 2), None);
+        assert_eq!(offset(u32::MAX - 4, 4, 1, 1), None);
+    }
+}
@@ -0,0 +1,200 @@
+//! The "Open with…" view, listing every application that can open a type
+
+use {
+    crate::{
+        file_uri, icons, mimeapps,
+        xdg_desktop_file::{args_from_exec_string, localized},
+    },
+    egui_sf2g::egui,
+    std::{
+        borrow::Cow,
+        ffi::{OsStr, OsString},
+        path::PathBuf,
+    },
+};
+
+/// Size icons are shown at in the list
+const ICON_SIZE: f32 = 24.;
+
+pub struct Chooser {
+    /// What's passed to the chosen application
+    launch_arg: OsString,
+    mime: String,
+    entries: Vec<Entry>,
+    query: String,
+    /// Index into the entries matching `query`
+    selected: usize,
+    /// Whether the search field got focus yet
+    focused: bool,
+}
+
+struct Entry {
+    id: String,
+    name: String,
+    generic_name: Option<String>,
+    exec: String,
+    icon: Option<PathBuf>,
+    is_default: bool,
+    /// The parent type the application was found for
+    via: Option<String>,
+}
+
+/// What the user did in the chooser
+pub enum Choice {
+    /// Launch this command line
+    Launch(String, Vec<OsString>),
+    /// The `Exec` key of the chosen application couldn't be parsed
+    InvalidExec(String),
+    /// Leave the chooser
+    Back,
+}
+
+impl Chooser {
+    pub fn new(launch_arg: &OsStr, mime: &str) -> Self {
+        let default = mimeapps::effective_default(mime);
+        let entries = mimeapps::handlers(mime)
+            .into_iter()
+            .filter_map(|handler| {
+                let map = &handler.desktop_map;
+                Some(Entry {
+                    name: localized(map, "Name").unwrap_or(&handler.id).to_string(),
+                    generic_name: localized(map, "GenericName").map(str::to_string),
+                    // Nothing to launch without it
+                    exec: map.get("Exec")?.clone(),
+                    icon: map
+                        .get("Icon")
+                        .and_then(|icon| icons::get().find(icon, ICON_SIZE as u32)),
+                    is_default: default.as_deref() == Some(handler.id.as_str()),
+                    via: handler.via,
+                    id: handler.id,
+                })
+            })
+            .collect();
+        Self {
+            launch_arg: launch_arg.to_owned(),
+            mime: mime.to_owned(),
+            entries,
+            query: String::new(),
+            selected: 0,
+            focused: false,
+        }
+    }
+
+    /// Indices of the entries matching the search query, case insensitively
+    fn matching(&self) -> Vec<usize> {
+        let query = self.query.to_lowercase();
+        (0..self.entries.len())
+            .filter(|&i| {
+                let entry = &self.entries[i];
+                [
+                    Some(entry.name.as_str()),
+                    entry.generic_name.as_deref(),
+                    Some(entry.id.as_str()),
+                ]
+                .into_iter()
+                .flatten()
+                .any(|text| text.to_lowercase().contains(&query))
+            })
+            .collect()
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<Choice> {
+        ui.heading(format!("Open {} with", self.mime));
+        // Taken before the search field sees them, so they move the selection instead
+        let [up, down, enter, esc] = ui.input_mut(|inp| {
+            [
+                egui::Key::ArrowUp,
+                egui::Key::ArrowDown,
+                egui::Key::Enter,
+                egui::Key::Escape,
+            ]
+            .map(|key| inp.consume_key(egui::Modifiers::NONE, key))
+        });
+        let search = ui.add(
+            egui::TextEdit::singleline(&mut self.query)
+                .hint_text("Search applications")
+                .desired_width(f32::INFINITY),
+        );
+        if !self.focused {
+            search.request_focus();
+            self.focused = true;
+        }
+        if search.changed() {
+            self.selected = 0;
+        }
+        let matching = self.matching();
+        let count = matching.len();
+        if down && self.selected + 1 < count {
+            self.selected += 1;
+        }
+        if up {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        let mut chosen = enter.then_some(self.selected);
+        egui::ScrollArea::vertical()
+            .max_height(320.)
+            .show(ui, |ui| {
+                if count == 0 {
+                    ui.label("No matching applications");
+                }
+                for (i, entry) in matching.iter().map(|&idx| &self.entries[idx]).enumerate() {
+                    let row = ui.horizontal(|ui| {
+                        match &entry.icon {
+                            Some(path) => {
+                                ui.add(
+                                    egui::Image::new(egui::ImageSource::Uri(Cow::Owned(file_uri(
+                                        path,
+                                    ))))
+                                    .fit_to_exact_size(egui::vec2(ICON_SIZE, ICON_SIZE)),
+                                );
+                            }
+                            None => {
+                                ui.add_space(ICON_SIZE + ui.spacing().item_spacing.x);
+                            }
+                        }
+                        let mut text = entry.name.clone();
+                        if entry.is_default {
+                            text.push_str(" (default)");
+                        }
+                        let label = ui.selectable_label(i == self.selected, text);
+                        ui.weak(&entry.id);
+                        if let Some(parent) = &entry.via {
+                            ui.weak(format!("for {parent}"));
+                        }
+                        label
+                    });
+                    let label = row.inner;
+                    if label.clicked() {
+                        self.selected = i;
+                    }
+                    if label.double_clicked() {
+                        chosen = Some(i);
+                    }
+                    if i == self.selected && (up || down) {
+                        label.scroll_to_me(None);
+                    }
+                }
+            });
+        ui.separator();
+        let mut back = esc;
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(count > 0, egui::Button::new("✔ Open (Enter)"))
+                .clicked()
+            {
+                chosen = Some(self.selected);
+            }
+            if ui.button("⏴ Back (Escape)").clicked() {
+                back = true;
+            }
+        });
+        if back {
+            return Some(Choice::Back);
+        }
+        let entry = &self.entries[*matching.get(chosen?)?];
+        Some(match args_from_exec_string(&entry.exec, &self.launch_arg) {
+            Some((to_exec, args)) => Choice::Launch(to_exec, args),
+            None => Choice::InvalidExec(entry.exec.clone()),
+        })
+    }
+}
@@ -0,0 +1,880 @@
+//! Command line parsing, compatible with `xdg-open`
+
+use {
+    std::{
+        ffi::{OsStr, OsString},
+        os::unix::ffi::OsStrExt,
+        path::Path,
+    },
+    thiserror::Error,
+};
+
+pub const USAGE: &str = "\
+rusty-open - opens a file or URL in the user's preferred application
+
+Usage:
+  rusty-open [--no-prompt | --dry-run | --explain] [--json] [--mime <type>] [--with <app>]
+             { file | URL | - }
+  rusty-open [--no-prompt | --dry-run | --explain] [--json] --reveal <path>
+  rusty-open handlers [--json] { file | URL | MIME type }
+  rusty-open mime query { filetype <file> | default <type> }
+  rusty-open mime default <app.desktop> <type>...
+  rusty-open mime install [--mode user|system] [--novendor] <package.xml>
+  rusty-open mime uninstall [--mode user|system] <package.xml>
+  rusty-open email [--cc <addr>] [--bcc <addr>] [--subject <text>] [--body <text>]
+                   [--attach <file>]... { address... | mailto-uri }
+  rusty-open settings { get | check | set } default-web-browser [<app.desktop>]
+  rusty-open settings { get | check | set } default-url-scheme-handler <scheme>
+                      [<app.desktop>]
+  rusty-open settings --list
+  rusty-open { --help | --manual | --version }
+";
+
+pub const MANUAL: &str = "\
+rusty-open - opens a file or URL in the user's preferred application
+
+Synopsis
+  rusty-open [--no-prompt | --dry-run | --explain] [--json] [--mime <type>] [--with <app>]
+             { file | URL | - }
+  rusty-open [--no-prompt | --dry-run | --explain] [--json] --reveal <path>
+  rusty-open handlers [--json] { file | URL | MIME type }
+  rusty-open mime query { filetype <file> | default <type> }
+  rusty-open mime default <app.desktop> <type>...
+  rusty-open mime install [--mode user|system] [--novendor] <package.xml>
+  rusty-open mime uninstall [--mode user|system] <package.xml>
+  rusty-open email [--cc <addr>] [--bcc <addr>] [--subject <text>] [--body <text>]
+                   [--attach <file>]... { address... | mailto-uri }
+  rusty-open settings { get | check | set } default-web-browser [<app.desktop>]
+  rusty-open settings { get | check | set } default-url-scheme-handler <scheme>
+                      [<app.desktop>]
+  rusty-open settings --list
+  rusty-open { --help | --manual | --version }
+
+Description
+  rusty-open opens a file or URL in the user's preferred application. If a URL is provided,
+  the URL will be opened in the handler registered for its scheme. If a file is provided,
+  the file will be opened in the preferred application for files of that type.
+  `-` reads the content to open from standard input.
+
+  Before launching, the resolved command is shown for confirmation, unless prompting is
+  turned off with --no-prompt, RUSTY_OPEN_NO_PROMPT or the NoPrompt config setting.
+  Errors, and types without a default application, are always shown in a window.
+  With a countdown configured (Countdown in [General], or per type in [Countdown], e.g.
+  `image/*=2` or `x-scheme-handler/https=3`), the confirmation window launches by itself once
+  it runs out. Escape, or any click or key press, stops it.
+  Icons are taken from the desktop's icon theme (kdeglobals on Plasma, GSettings, GTK's
+  settings.ini or XSettings elsewhere), unless IconTheme in [General] names another one.
+  From the confirmation window, and when there is no default application, Ctrl+O opens a
+  list of every application that can open the type, including those for parent types.
+  Without a default, a command can also be typed in, completed from $PATH and the installed
+  applications (Up/Down and Tab pick a completion). %f in the command stands for the file,
+  which is appended otherwise.
+  The command in the confirmation window can be edited before running it, e.g. to add an
+  option just this once. An edited command can also be saved for the type, in the
+  [Overrides] group of the config file (`text/markdown=kate --line 1 %f`). Overrides are
+  desktop file IDs or command lines, like --with, and take precedence over mimeapps.list.
+  For local files, the confirmation window has a Preview section: a thumbnail of PNG, JPEG
+  and SVG images, the first lines of text files, or the size, modification time and
+  permissions of anything else. It's only read once expanded, and only partially.
+  Thumbnails file managers made (in ~/.cache/thumbnails, per the freedesktop thumbnail spec)
+  are shown next to the type, as long as they are up to date with the file.
+
+  `rusty-open handlers` lists every application that can open a file, URL or MIME type,
+  along with its name, whether it's the default, and the file the association comes from.
+  Applications for parent types (e.g. text/plain for text/markdown) are listed last.
+  The default, the same one opening uses (including [Overrides]), is marked with `*`.
+  Exits with 2 if there are no applications for the type.
+
+  `rusty-open mime` works like xdg-mime from xdg-utils, using the shared-mime-info database
+  (mime.cache, or the text files when it's missing or older than them) and mimeapps.list
+  files directly. `mime default` writes $XDG_CONFIG_HOME/mimeapps.list.
+  `mime install` copies a shared-mime-info package to the packages directory of
+  $XDG_DATA_HOME/mime (or the first directory of $XDG_DATA_DIRS with --mode system) and runs
+  update-mime-database. Package file names need a vendor prefix (e.g. `acme-foo.xml`),
+  unless --novendor is given.
+
+  `rusty-open email` works like xdg-email: it opens a composer in the handler of mailto:
+  URLs. --cc, --bcc and --attach may be given several times. Thunderbird and KMail get
+  attachments as their own command line options, other clients (e.g. Evolution) as attach=
+  parameters of the mailto: URL.
+
+  `rusty-open settings` works like xdg-settings. `set` and `check` take the desktop file ID
+  after the property. Setting the web browser makes it the default for http:, https:,
+  text/html and application/xhtml+xml at once. Defaults are written to
+  $XDG_CONFIG_HOME/mimeapps.list and any desktop specific list next to it
+  (e.g. kde-mimeapps.list), and on KDE Plasma also to BrowserApplication in kdeglobals.
+
+Invocation names
+  Installed under another name (e.g. as a symlink), rusty-open takes on that command line:
+  xdg-open      Opens a single file or URL
+  xdg-mime      Same as `rusty-open mime`
+  xdg-email     Same as `rusty-open email`
+  xdg-settings  Same as `rusty-open settings`
+  kde-open, gio-open, exo-open
+                Open each of the files or URLs given
+
+Options
+  --mime <type>   Open as MIME type <type>, instead of detecting the type
+  --with <app>    Open with <app> instead of the default application. <app> is a desktop
+                  file ID (e.g. org.kde.kate), the path of a desktop file, or a command
+                  line, which may use %f for the file (it's appended otherwise)
+  --reveal <path> Show <path> in its containing folder
+  --no-prompt     Launch right away, without asking for confirmation
+  --dry-run       Print what would be launched, and why, instead of launching
+  --explain       Like --dry-run, but also print every resolution step
+                  (detection, association files consulted, helper invocations)
+  --json          Print --dry-run, --explain or handlers output as JSON.
+                  Implies --dry-run when opening.
+  --help          Show command synopsis
+  --manual        Show this manual page
+  --version       Show the version
+
+Exit codes
+  0  Command successful
+  1  Error in command line syntax
+  2  One of the files passed on the command line did not exist
+  3  A required tool could not be found
+  4  The action failed
+  5  No permission to read one of the files passed on the command line
+
+Environment
+  RUSTY_OPEN_HELPER_TIMEOUT  Seconds to wait for helper programs like xdg-mime, which
+                             are only used when there is no shared-mime-info database
+  RUSTY_OPEN_BARE_HOSTNAMES  Open arguments like `example.com/page` as https: URLs
+  RUSTY_OPEN_NO_PROMPT       Launch right away, like --no-prompt
+  RUSTY_OPEN_COUNTDOWN       Seconds the confirmation window waits before launching by
+                             itself, for every type (0 waits for confirmation)
+
+Files
+  $XDG_CONFIG_HOME/rusty-open/config
+";
+
+/// Exit codes, as documented for `xdg-open`
+pub const EXIT_SYNTAX: i32 = 1;
+pub const EXIT_NOT_FOUND: i32 = 2;
+pub const EXIT_TOOL_MISSING: i32 = 3;
+pub const EXIT_FAILED: i32 = 4;
+pub const EXIT_NO_PERMISSION: i32 = 5;
+
+pub struct Cli {
+    pub command: Command,
+    /// Launch without asking for confirmation
+    pub no_prompt: bool,
+    /// Print the resolution instead of launching
+    pub report: Option<Report>,
+    /// Print the report as JSON
+    pub json: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Report {
+    /// Summary of the resolution
+    DryRun,
+    /// Summary, along with every step taken
+    Explain,
+}
+
+pub enum Command {
+    /// Nothing to open was given
+    NoArgs,
+    Open {
+        /// Path or URL, or `-` for standard input
+        target: OsString,
+        mime: Option<String>,
+        /// Desktop ID or command to open with, instead of the default application
+        with: Option<String>,
+    },
+    Reveal(OsString),
+    /// List the applications for a file, URL or MIME type
+    Handlers(OsString),
+    /// `xdg-mime` compatible subcommands
+    Mime(MimeCommand),
+    /// Compose a mail, like `xdg-email`
+    Email(Email),
+    /// Get or set default applications, like `xdg-settings`
+    Settings(SettingsCommand),
+    /// Open each of several files or URLs, for openers that accept more than one
+    OpenAll(Vec<OsString>),
+    Help,
+    Manual,
+    Version,
+}
+
+pub enum MimeCommand {
+    /// Print the type of a file
+    QueryFiletype(OsString),
+    /// Print the default application of a type
+    QueryDefault(String),
+    /// Make an application the default for types
+    Default {
+        desktop_id: String,
+        mimes: Vec<String>,
+    },
+    /// Add a MIME type description file to the database
+    Install {
+        mode: InstallMode,
+        novendor: bool,
+        file: OsString,
+    },
+    /// Remove a MIME type description file from the database
+    Uninstall { mode: InstallMode, file: OsString },
+}
+
+/// What to put in a new mail
+#[derive(Default)]
+pub struct Email {
+    /// Addresses, or a complete `mailto:` URL
+    pub to: Vec<String>,
+    pub cc: Vec<String>,
+    pub bcc: Vec<String>,
+    pub subject: Option<String>,
+    pub body: Option<String>,
+    /// Files to attach
+    pub attach: Vec<OsString>,
+}
+
+pub enum SettingsCommand {
+    Get(Setting),
+    Set(Setting, String),
+    /// Print whether a setting has the given value
+    Check(Setting, String),
+    /// Print the known settings
+    List,
+}
+
+pub enum Setting {
+    DefaultWebBrowser,
+    /// The handler of a URL scheme
+    DefaultUrlSchemeHandler(String),
+}
+
+/// Whether to install for the current user only, or for everyone
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum InstallMode {
+    User,
+    System,
+}
+
+#[derive(Error, Debug)]
+pub enum CliError {
+    #[error("unknown option '{0}'")]
+    UnknownOption(String),
+    #[error("option '{0}' requires an argument")]
+    MissingValue(&'static str),
+    #[error("value of '{0}' must be valid UTF-8")]
+    NotUtf8(&'static str),
+    #[error("unexpected argument '{0}'")]
+    UnexpectedArg(String),
+    #[error("unknown command '{0}'")]
+    UnknownCommand(String),
+    #[error("{0} argument missing")]
+    MissingArg(&'static str),
+    #[error("invalid value '{value}' for option '{option}'")]
+    InvalidValue { option: &'static str, value: String },
+}
+
+impl CliError {
+    /// The exit code for the error. Like for the xdg-utils, any error in the command line is
+    /// a syntax error.
+    pub fn exit_code(&self) -> i32 {
+        EXIT_SYNTAX
+    }
+}
+
+/// Parse the command line arguments, excluding the program name
+fn parse(args: impl IntoIterator<Item = OsString>) -> Result<Cli, CliError> {
+    let mut args = args.into_iter().peekable();
+    // Only a subcommand if something follows, so a file named like one can still be opened
+    let subcommand = args.next_if(|arg| {
+        ["handlers", "mime", "email", "settings"]
+            .iter()
+            .any(|name| arg == name)
+    });
+    if let Some(name) = &subcommand
+        && args.peek().is_some()
+    {
+        return match name.to_str() {
+            Some("handlers") => parse_handlers(args),
+            Some("mime") => parse_mime(args),
+            Some("email") => parse_email(args),
+            _ => parse_settings(args),
+        };
+    }
+    parse_open(subcommand.into_iter().chain(args))
+}
+
+/// Parse the options and target of opening something, which is all `xdg-open` understands
+fn parse_open(mut args: impl Iterator<Item = OsString>) -> Result<Cli, CliError> {
+    let mut target = None;
+    let mut mime = None;
+    let mut with = None;
+    let mut reveal = None;
+    let mut no_prompt = false;
+    let mut report = None;
+    let mut json = false;
+    let mut options_done = false;
+    while let Some(arg) = args.next() {
+        let is_option = !options_done && arg.as_bytes().starts_with(b"-") && arg != "-";
+        if !is_option {
+            if target.is_some() {
+                return Err(CliError::UnexpectedArg(arg.to_string_lossy().into_owned()));
+            }
+            target = Some(arg);
+            continue;
+        }
+        let Some(opt) = arg.to_str() else {
+            return Err(CliError::UnknownOption(arg.to_string_lossy().into_owned()));
+        };
+        // Both `--opt value` and `--opt=value` are accepted
+        let (name, inline_value) = match opt.split_once('=') {
+            Some((name, value)) => (name, Some(OsString::from(value))),
+            None => (opt, None),
+        };
+        let mut value = |name: &'static str| {
+            inline_value
+                .clone()
+                .or_else(|| args.next())
+                .ok_or(CliError::MissingValue(name))
+        };
+        match name {
+            "--" => options_done = true,
+            "--help" | "-h" => return Ok(Cli::new(Command::Help)),
+            "--manual" => return Ok(Cli::new(Command::Manual)),
+            "--version" => return Ok(Cli::new(Command::Version)),
+            "--no-prompt" => no_prompt = true,
+            "--dry-run" => report = report.max(Some(Report::DryRun)),
+            "--explain" => report = Some(Report::Explain),
+            "--json" => json = true,
+            "--mime" => {
+                mime = Some(
+                    value("--mime")?
+                        .into_string()
+                        .map_err(|_| CliError::NotUtf8("--mime"))?,
+                );
+            }
+            "--with" => {
+                with = Some(
+                    value("--with")?
+                        .into_string()
+                        .map_err(|_| CliError::NotUtf8("--with"))?,
+                );
+            }
+            "--reveal" => reveal = Some(value("--reveal")?),
+            _ => return Err(CliError::UnknownOption(opt.to_string())),
+        }
+    }
+    let command = match (reveal, target) {
+        (Some(path), None) => Command::Reveal(path),
+        (Some(_), Some(target)) => {
+            return Err(CliError::UnexpectedArg(
+                target.to_string_lossy().into_owned(),
+            ));
+        }
+        (None, Some(target)) => Command::Open { target, mime, with },
+        (None, None) => Command::NoArgs,
+    };
+    if json && report.is_none() {
+        report = Some(Report::DryRun);
+    }
+    Ok(Cli {
+        command,
+        no_prompt,
+        report,
+        json,
+    })
+}
+
+fn parse_handlers(args: impl Iterator<Item = OsString>) -> Result<Cli, CliError> {
+    let mut query = None;
+    let mut json = false;
+    let mut options_done = false;
+    for arg in args {
+        if !options_done && arg.as_bytes().starts_with(b"-") {
+            match arg.to_str() {
+                Some("--") => options_done = true,
+                Some("--json") => json = true,
+                _ => return Err(CliError::UnknownOption(arg.to_string_lossy().into_owned())),
+            }
+        } else if query.is_some() {
+            return Err(CliError::UnexpectedArg(arg.to_string_lossy().into_owned()));
+        } else {
+            query = Some(arg);
+        }
+    }
+    let command = query.map_or(Command::NoArgs, Command::Handlers);
+    Ok(Cli {
+        json,
+        ..Cli::new(command)
+    })
+}
+
+/// Parse the arguments following `email`, the same way `xdg-email` does
+fn parse_email(mut args: impl Iterator<Item = OsString>) -> Result<Cli, CliError> {
+    let mut email = Email::default();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &'static str| {
+            args.next()
+                .ok_or(CliError::MissingValue(name))?
+                .into_string()
+                .map_err(|_| CliError::NotUtf8(name))
+        };
+        match arg.to_str() {
+            Some("--help") => return Ok(Cli::new(Command::Help)),
+            Some("--manual") => return Ok(Cli::new(Command::Manual)),
+            Some("--version") => return Ok(Cli::new(Command::Version)),
+            // Everything is UTF-8 anyway
+            Some("--utf8") => {}
+            Some("--cc") => email.cc.push(value("--cc")?),
+            Some("--bcc") => email.bcc.push(value("--bcc")?),
+            Some("--subject") => email.subject = Some(value("--subject")?),
+            Some("--body") => email.body = Some(value("--body")?),
+            Some("--attach") => {
+                email
+                    .attach
+                    .push(args.next().ok_or(CliError::MissingValue("--attach"))?);
+            }
+            Some(opt) if opt.starts_with("--") => {
+                return Err(CliError::UnknownOption(opt.to_string()));
+            }
+            _ => email.to.push(
+                arg.into_string()
+                    .map_err(|_| CliError::NotUtf8("address"))?,
+            ),
+        }
+    }
+    Ok(Cli::new(Command::Email(email)))
+}
+
+/// Parse the arguments following `settings`, the same way `xdg-settings` does
+fn parse_settings(args: impl Iterator<Item = OsString>) -> Result<Cli, CliError> {
+    let mut words = Vec::new();
+    for arg in args {
+        match arg.to_str() {
+            Some("--help") => return Ok(Cli::new(Command::Help)),
+            Some("--manual") => return Ok(Cli::new(Command::Manual)),
+            Some("--version") => return Ok(Cli::new(Command::Version)),
+            Some("--list") => return Ok(Cli::new(Command::Settings(SettingsCommand::List))),
+            Some(opt) if opt.starts_with("--") => {
+                return Err(CliError::UnknownOption(opt.to_string()));
+            }
+            Some(word) => words.push(word.to_string()),
+            None => return Err(CliError::NotUtf8("argument")),
+        }
+    }
+    let mut words = words.into_iter();
+    let mut next = |what: &'static str| words.next().ok_or(CliError::MissingArg(what));
+    let action = next("command")?;
+    let setting = match next("property")?.as_str() {
+        "default-web-browser" => Setting::DefaultWebBrowser,
+        "default-url-scheme-handler" => Setting::DefaultUrlSchemeHandler(next("scheme")?),
+        other => return Err(CliError::UnknownCommand(other.to_string())),
+    };
+    let command = match action.as_str() {
+        "get" => SettingsCommand::Get(setting),
+        "set" => SettingsCommand::Set(setting, next("value")?),
+        "check" => SettingsCommand::Check(setting, next("value")?),
+        other => return Err(CliError::UnknownCommand(other.to_string())),
+    };
+    if let Some(extra) = words.next() {
+        return Err(CliError::UnexpectedArg(extra));
+    }
+    Ok(Cli::new(Command::Settings(command)))
+}
+
+/// The command line flavours understood, chosen by the name the binary is invoked as
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Flavor {
+    RustyOpen,
+    XdgOpen,
+    XdgMime,
+    XdgEmail,
+    XdgSettings,
+    /// `kde-open`, `gio-open` and `exo-open`, which take any number of locations
+    Opener,
+}
+
+impl Flavor {
+    /// The flavour for a binary called `argv0`, which may be a path
+    pub fn from_argv0(argv0: &OsStr) -> Self {
+        match Path::new(argv0).file_name().and_then(OsStr::to_str) {
+            Some("xdg-open") => Self::XdgOpen,
+            Some("xdg-mime") => Self::XdgMime,
+            Some("xdg-email") => Self::XdgEmail,
+            Some("xdg-settings") => Self::XdgSettings,
+            Some("kde-open" | "kde-open5" | "kde-open6" | "gio-open" | "exo-open") => Self::Opener,
+            _ => Self::RustyOpen,
+        }
+    }
+}
+
+/// Parse the command line arguments, excluding the program name, as `flavor` would
+pub fn parse_as(flavor: Flavor, args: impl IntoIterator<Item = OsString>) -> Result<Cli, CliError> {
+    let args = args.into_iter();
+    match flavor {
+        Flavor::RustyOpen => parse(args),
+        Flavor::XdgOpen => parse_open(args),
+        Flavor::XdgMime => parse_mime(args),
+        Flavor::XdgEmail => parse_email(args),
+        Flavor::XdgSettings => parse_settings(args),
+        Flavor::Opener => parse_locations(args),
+    }
+}
+
+/// Parse a list of locations, as taken by `kde-open`, `gio open` and `exo-open`
+fn parse_locations(args: impl Iterator<Item = OsString>) -> Result<Cli, CliError> {
+    let mut targets = Vec::new();
+    let mut options_done = false;
+    for arg in args {
+        if options_done || !arg.as_bytes().starts_with(b"-") || arg == "-" {
+            targets.push(arg);
+            continue;
+        }
+        match arg.to_str() {
+            Some("--") => options_done = true,
+            Some("--help" | "-h") => return Ok(Cli::new(Command::Help)),
+            Some("--manual") => return Ok(Cli::new(Command::Manual)),
+            Some("--version" | "-v") => return Ok(Cli::new(Command::Version)),
+            _ => return Err(CliError::UnknownOption(arg.to_string_lossy().into_owned())),
+        }
+    }
+    let command = if targets.len() > 1 {
+        Command::OpenAll(targets)
+    } else {
+        match targets.pop() {
+            Some(target) => Command::Open {
+                target,
+                mime: None,
+                with: None,
+            },
+            None => Command::NoArgs,
+        }
+    };
+    Ok(Cli::new(command))
+}
+
+/// Parse the arguments following `mime`, the same way `xdg-mime` does
+fn parse_mime(args: impl Iterator<Item = OsString>) -> Result<Cli, CliError> {
+    let mut mode = InstallMode::User;
+    let mut novendor = false;
+    let mut words = Vec::new();
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        match arg.to_str() {
+            Some("--help") => return Ok(Cli::new(Command::Help)),
+            Some("--manual") => return Ok(Cli::new(Command::Manual)),
+            Some("--version") => return Ok(Cli::new(Command::Version)),
+            Some("--novendor") => novendor = true,
+            Some("--mode") => {
+                let value = args.next().ok_or(CliError::MissingValue("--mode"))?;
+                mode = match value.to_str() {
+                    Some("user") => InstallMode::User,
+                    Some("system") => InstallMode::System,
+                    _ => {
+                        return Err(CliError::InvalidValue {
+                            option: "--mode",
+                            value: value.to_string_lossy().into_owned(),
+                        });
+                    }
+                };
+            }
+            Some(opt) if opt.starts_with("--") => {
+                return Err(CliError::UnknownOption(opt.to_string()));
+            }
+            _ => words.push(arg),
+        }
+    }
+    let mut words = words.into_iter();
+    let mut next_utf8 = |what: &'static str| {
+        words
+            .next()
+            .ok_or(CliError::MissingArg(what))?
+            .into_string()
+            .map_err(|_| CliError::NotUtf8(what))
+    };
+    let command = match next_utf8("command")?.as_str() {
+        "query" => match next_utf8("query type")?.as_str() {
+            "filetype" => {
+                MimeCommand::QueryFiletype(words.next().ok_or(CliError::MissingArg("FILE"))?)
+            }
+            "default" => MimeCommand::QueryDefault(next_utf8("mimetype")?),
+            other => return Err(CliError::UnknownCommand(format!("query {other}"))),
+        },
+        "default" => {
+            let desktop_id = next_utf8("application")?;
+            let mimes = words
+                .map(|mime| {
+                    mime.into_string()
+                        .map_err(|_| CliError::NotUtf8("mimetype"))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if mimes.is_empty() {
+                return Err(CliError::MissingArg("mimetype"));
+            }
+            MimeCommand::Default { desktop_id, mimes }
+        }
+        "install" => MimeCommand::Install {
+            mode,
+            novendor,
+            file: words.next().ok_or(CliError::MissingArg("mimetypes-file"))?,
+        },
+        "uninstall" => MimeCommand::Uninstall {
+            mode,
+            file: words.next().ok_or(CliError::MissingArg("mimetypes-file"))?,
+        },
+        other => return Err(CliError::UnknownCommand(other.to_string())),
+    };
+    Ok(Cli::new(Command::Mime(command)))
+}
+
+impl Cli {
+    fn new(command: Command) -> Self {
+        Self {
+            command,
+            no_prompt: false,
+            report: None,
+            json: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What parsing `args` as `flavor` comes to, in short
+    fn outcome(flavor: Flavor, args: &[&str]) -> String {
+        let cli = match parse_as(flavor, args.iter().map(OsString::from)) {
+            Ok(cli) => cli,
+            Err(err) => return format!("exit {}", err.exit_code()),
+        };
+        let list = |items: &[OsString]| {
+            items
+                .iter()
+                .map(|item| item.to_string_lossy())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let mut outcome = match cli.command {
+            Command::NoArgs => "no args".to_string(),
+            Command::Open { target, mime, with } => format!(
+                "open {}{}{}",
+                target.to_string_lossy(),
+                mime.map(|mime| format!(" as {mime}")).unwrap_or_default(),
+                with.map(|with| format!(" with {with}")).unwrap_or_default(),
+            ),
+            Command::OpenAll(targets) => format!("open all {}", list(&targets)),
+            Command::Reveal(path) => format!("reveal {}", path.to_string_lossy()),
+            Command::Handlers(query) => format!("handlers {}", query.to_string_lossy()),
+            Command::Mime(MimeCommand::QueryFiletype(file)) => {
+                format!("filetype {}", file.to_string_lossy())
+            }
+            Command::Mime(MimeCommand::QueryDefault(mime)) => format!("default of {mime}"),
+            Command::Mime(MimeCommand::Default { desktop_id, mimes }) => {
+                format!("default {desktop_id} for {}", mimes.join(" "))
+            }
+            Command::Mime(MimeCommand::Install { file, .. }) => {
+                format!("install {}", file.to_string_lossy())
+            }
+            Command::Mime(MimeCommand::Uninstall { file, .. }) => {
+                format!("uninstall {}", file.to_string_lossy())
+            }
+            Command::Email(email) => format!(
+                "email {} cc {} attach {}",
+                email.to.join(" "),
+                email.cc.join(" "),
+                list(&email.attach)
+            ),
+            Command::Settings(SettingsCommand::Get(_)) => "settings get".to_string(),
+            Command::Settings(SettingsCommand::Set(_, value)) => format!("settings set {value}"),
+            Command::Settings(SettingsCommand::Check(_, value)) => {
+                format!("settings check {value}")
+            }
+            Command::Settings(SettingsCommand::List) => "settings list".to_string(),
+            Command::Help => "help".to_string(),
+            Command::Manual => "manual".to_string(),
+            Command::Version => "version".to_string(),
+        };
+        match cli.report {
+            Some(Report::DryRun) => outcome.push_str(" (dry run)"),
+            Some(Report::Explain) => outcome.push_str(" (explain)"),
+            None => {}
+        }
+        outcome
+    }
+
+    fn check(flavor: Flavor, table: &[(&[&str], &str)]) {
+        for (args, expected) in table {
+            assert_eq!(outcome(flavor, args), *expected, "{args:?}");
+        }
+    }
+
+    #[test]
+    fn flavors_by_name() {
+        for (argv0, flavor) in [
+            ("/usr/local/bin/xdg-open", Flavor::XdgOpen),
+            ("xdg-mime", Flavor::XdgMime),
+            ("xdg-email", Flavor::XdgEmail),
+            ("xdg-settings", Flavor::XdgSettings),
+            ("kde-open5", Flavor::Opener),
+            ("exo-open", Flavor::Opener),
+            ("rusty-open", Flavor::RustyOpen),
+            ("something-else", Flavor::RustyOpen),
+        ] {
+            assert!(Flavor::from_argv0(OsStr::new(argv0)) == flavor, "{argv0}");
+        }
+    }
+
+    #[test]
+    fn rusty_open() {
+        check(
+            Flavor::RustyOpen,
+            &[
+                (&[], "no args"),
+                (&["a.txt"], "open a.txt"),
+                (&["-"], "open -"),
+                (
+                    &["--mime=text/plain", "--with", "kate", "a"],
+                    "open a as text/plain with kate",
+                ),
+                (&["--json", "a"], "open a (dry run)"),
+                (&["--explain", "--dry-run", "a"], "open a (explain)"),
+                (&["--reveal", "a"], "reveal a"),
+                (&["--", "-a"], "open -a"),
+                (&["--", "--help"], "open --help"),
+                (&["a", "b"], "exit 1"),
+                (&["--reveal", "a", "b"], "exit 1"),
+                (&["--bogus", "a"], "exit 1"),
+                (&["-x"], "exit 1"),
+                (&["--mime"], "exit 1"),
+                (&["--help", "a"], "help"),
+                (&["handlers", "--json", "a"], "handlers a"),
+                // Subcommand names alone are files
+                (&["mime"], "open mime"),
+                (&["settings", "--list"], "settings list"),
+            ],
+        );
+    }
+
+    #[test]
+    fn xdg_open() {
+        check(
+            Flavor::XdgOpen,
+            &[
+                (&[], "no args"),
+                (&["https://example.com"], "open https://example.com"),
+                (&["--", "-file"], "open -file"),
+                (&["a", "b"], "exit 1"),
+                (&["--unknown", "a"], "exit 1"),
+                (&["--version"], "version"),
+                (&["--manual"], "manual"),
+                // Not a subcommand for xdg-open
+                (&["mime", "query"], "exit 1"),
+            ],
+        );
+    }
+
+    #[test]
+    fn xdg_mime() {
+        check(
+            Flavor::XdgMime,
+            &[
+                (&["query", "filetype", "a.png"], "filetype a.png"),
+                (&["query", "default", "text/plain"], "default of text/plain"),
+                (
+                    &["default", "kate.desktop", "text/plain", "text/markdown"],
+                    "default kate.desktop for text/plain text/markdown",
+                ),
+                (
+                    &["install", "--mode", "user", "--novendor", "a.xml"],
+                    "install a.xml",
+                ),
+                (&["uninstall", "a.xml"], "uninstall a.xml"),
+                (&["query"], "exit 1"),
+                (&["query", "filetype"], "exit 1"),
+                (&["default", "kate.desktop"], "exit 1"),
+                (&["frobnicate", "x"], "exit 1"),
+                (&["install", "--mode", "everyone", "a.xml"], "exit 1"),
+                (&["--bogus", "query", "default", "text/plain"], "exit 1"),
+                (&["--", "query", "default", "text/plain"], "exit 1"),
+                (&["--help"], "help"),
+            ],
+        );
+    }
+
+    #[test]
+    fn xdg_email() {
+        check(
+            Flavor::XdgEmail,
+            &[
+                (&["a@example.com"], "email a@example.com cc  attach "),
+                (
+                    &[
+                        "--cc",
+                        "c@example.com",
+                        "--attach",
+                        "f.pdf",
+                        "a@example.com",
+                        "b@example.com",
+                    ],
+                    "email a@example.com b@example.com cc c@example.com attach f.pdf",
+                ),
+                (
+                    &["--utf8", "mailto:a@example.com"],
+                    "email mailto:a@example.com cc  attach ",
+                ),
+                (&["--cc"], "exit 1"),
+                (&["--bogus", "a@example.com"], "exit 1"),
+                (&["--", "a@example.com"], "exit 1"),
+                (&["--version"], "version"),
+            ],
+        );
+    }
+
+    #[test]
+    fn xdg_settings() {
+        check(
+            Flavor::XdgSettings,
+            &[
+                (&["get", "default-web-browser"], "settings get"),
+                (
+                    &["set", "default-web-browser", "firefox.desktop"],
+                    "settings set firefox.desktop",
+                ),
+                (
+                    &[
+                        "check",
+                        "default-url-scheme-handler",
+                        "irc",
+                        "hexchat.desktop",
+                    ],
+                    "settings check hexchat.desktop",
+                ),
+                (&["--list"], "settings list"),
+                (&["get"], "exit 1"),
+                (&["set", "default-web-browser"], "exit 1"),
+                (&["get", "default-web-browser", "extra"], "exit 1"),
+                (&["get", "default-pager"], "exit 1"),
+                (&["frob", "default-web-browser"], "exit 1"),
+                (&["--bogus", "get", "default-web-browser"], "exit 1"),
+                (&["--", "get", "default-web-browser"], "exit 1"),
+            ],
+        );
+    }
+
+    #[test]
+    fn openers() {
+        check(
+            Flavor::Opener,
+            &[
+                (&[], "no args"),
+                (&["a"], "open a"),
+                (&["a", "b", "-"], "open all a b -"),
+                (&["--", "-a", "--b"], "open all -a --b"),
+                (&["a", "--", "-b"], "open all a -b"),
+                (&["-v"], "version"),
+                (&["-h", "a"], "help"),
+                (&["--new-window", "a"], "exit 1"),
+            ],
+        );
+    }
+}
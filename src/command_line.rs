@@ -0,0 +1,83 @@
+//! The editable command line of the confirmation prompt
+
+use {
+    crate::xdg_desktop_file::quote_command,
+    egui_sf2g::egui,
+    std::ffi::{OsStr, OsString},
+};
+
+pub struct CommandLine {
+    /// The command as resolved, to go back to
+    original: String,
+    pub text: String,
+    /// Whether to save the edited command as the override for the type
+    pub save: bool,
+}
+
+impl CommandLine {
+    /// `None` if the command can't be edited as text, i.e. isn't valid UTF-8
+    pub fn new(to_exec: &str, args: &[OsString]) -> Option<Self> {
+        let mut words = vec![to_exec];
+        for arg in args {
+            words.push(arg.to_str()?);
+        }
+        let original = shlex::try_join(words).ok()?;
+        Some(Self {
+            text: original.clone(),
+            original,
+            save: false,
+        })
+    }
+
+    pub fn is_edited(&self) -> bool {
+        self.text != self.original
+    }
+
+    /// The program and arguments of the edited command, if it parses and isn't empty
+    pub fn argv(&self) -> Option<(String, Vec<OsString>)> {
+        let mut words = shlex::split(&self.text)?.into_iter();
+        let program = words.next()?;
+        Some((program, words.map(OsString::from).collect()))
+    }
+
+    /// The edited command for the `[Overrides]` config group, with `launch_arg` replaced by `%f`
+    /// so it works for any file
+    pub fn as_override(&self, launch_arg: &OsStr) -> Option<String> {
+        let words: Vec<String> = shlex::split(&self.text)?
+            .into_iter()
+            .map(|word| {
+                if *word == *launch_arg {
+                    "%f".to_string()
+                } else {
+                    word
+                }
+            })
+            .collect();
+        if words.is_empty() {
+            return None;
+        }
+        quote_command(&words)
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui, mime: &str) {
+        ui.vertical(|ui| {
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.text)
+                        .code_editor()
+                        .desired_width(480.),
+                );
+                if ui
+                    .add_enabled(self.is_edited(), egui::Button::new("⟲ Reset"))
+                    .clicked()
+                {
+                    self.text = self.original.clone();
+                }
+            });
+            ui.add_enabled(
+                self.is_edited(),
+                egui::Checkbox::new(&mut self.save, format!("Always use this for {mime}")),
+            );
+        });
+    }
+}
@@ -0,0 +1,190 @@
+//! The command field shown when there is no default application, with completion from
+//! `$PATH` and the installed desktop entries
+
+use {
+    crate::xdg_desktop_file::{
+        installed_desktop_files, localized, parse_desktop_file, quote_command,
+    },
+    egui_sf2g::egui,
+    std::{
+        collections::BTreeSet,
+        os::unix::fs::PermissionsExt,
+        path::{Path, PathBuf},
+    },
+};
+
+/// How many completions are listed at most
+const MAX_SUGGESTIONS: usize = 8;
+
+#[derive(Default)]
+pub struct CommandField {
+    pub text: String,
+    /// Loaded when the field is first shown
+    candidates: Option<Vec<Candidate>>,
+    /// Indices into `candidates` matching `text`
+    suggestions: Vec<usize>,
+    /// Index into `suggestions` picked with the arrow keys
+    highlighted: Option<usize>,
+    /// Whether the program of `text` exists
+    runnable: bool,
+}
+
+struct Candidate {
+    /// What goes into the field
+    command: String,
+    /// Application name, for desktop entries
+    name: Option<String>,
+}
+
+impl CommandField {
+    /// Whether the command can be run, i.e. its program exists
+    pub fn is_runnable(&self) -> bool {
+        self.runnable
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        let candidates = self.candidates.get_or_insert_with(load_candidates);
+        // Taken before the text field sees them, so they pick a suggestion instead
+        let [up, down, tab] = ui.input_mut(|inp| {
+            [egui::Key::ArrowUp, egui::Key::ArrowDown, egui::Key::Tab]
+                .map(|key| inp.consume_key(egui::Modifiers::NONE, key))
+        });
+        ui.vertical(|ui| {
+            let edit = ui.add(
+                egui::TextEdit::singleline(&mut self.text)
+                    .hint_text("e.g. kate, or gimp %f")
+                    .desired_width(320.),
+            );
+            let mut picked = None;
+            if edit.changed() {
+                self.suggestions = suggest(candidates, &self.text);
+                self.highlighted = None;
+            }
+            let count = self.suggestions.len();
+            if down && count > 0 {
+                self.highlighted = Some(self.highlighted.map_or(0, |i| (i + 1) % count));
+            }
+            if up && count > 0 {
+                self.highlighted = Some(
+                    self.highlighted
+                        .map_or(count - 1, |i| (i + count - 1) % count),
+                );
+            }
+            if tab && count > 0 {
+                picked = Some(self.highlighted.unwrap_or(0));
+            }
+            for (i, &idx) in self.suggestions.iter().enumerate() {
+                let candidate = &candidates[idx];
+                let text = match &candidate.name {
+                    Some(name) => format!("{name} ({})", candidate.command),
+                    None => candidate.command.clone(),
+                };
+                if ui
+                    .selectable_label(self.highlighted == Some(i), text)
+                    .clicked()
+                {
+                    picked = Some(i);
+                }
+            }
+            if let Some(i) = picked {
+                self.text = candidates[self.suggestions[i]].command.clone();
+                self.suggestions.clear();
+                self.highlighted = None;
+                edit.request_focus();
+            }
+            if edit.changed() || picked.is_some() {
+                self.runnable = command_exists(&self.text);
+            }
+        });
+    }
+}
+
+/// Candidates matching the program being typed, desktop entries first.
+///
+/// Once arguments are being typed, there's nothing left to complete.
+fn suggest(candidates: &[Candidate], text: &str) -> Vec<usize> {
+    let text = text.trim_start();
+    if text.is_empty() || text.contains(char::is_whitespace) {
+        return Vec::new();
+    }
+    let lower = text.to_lowercase();
+    let matches = |candidate: &Candidate| {
+        candidate.command.starts_with(text)
+            || candidate
+                .name
+                .as_ref()
+                .is_some_and(|name| name.to_lowercase().contains(&lower))
+    };
+    (0..candidates.len())
+        .filter(|&i| matches(&candidates[i]) && candidates[i].command != text)
+        .take(MAX_SUGGESTIONS)
+        .collect()
+}
+
+fn load_candidates() -> Vec<Candidate> {
+    let mut candidates = Vec::new();
+    for (_, path) in installed_desktop_files() {
+        let Ok(map) = parse_desktop_file(&path) else {
+            continue;
+        };
+        if map.get("NoDisplay").is_some_and(|v| v == "true")
+            || map.get("Hidden").is_some_and(|v| v == "true")
+        {
+            continue;
+        }
+        let (Some(exec), Some(name)) = (map.get("Exec"), localized(&map, "Name")) else {
+            continue;
+        };
+        if let Some(command) = exec_as_command(exec) {
+            candidates.push(Candidate {
+                command,
+                name: Some(name.to_string()),
+            });
+        }
+    }
+    let programs: BTreeSet<String> = path_dirs()
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .flatten()
+        .filter(|entry| is_executable(&entry.path()))
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect();
+    candidates.extend(programs.into_iter().map(|command| Candidate {
+        command,
+        name: None,
+    }));
+    candidates
+}
+
+/// An `Exec` line as a command for the field, without the field codes that only make sense
+/// for desktop entries (icon, name, location)
+fn exec_as_command(exec: &str) -> Option<String> {
+    let mut words = shlex::split(exec)?;
+    words.retain(|word| !["%i", "%c", "%k"].contains(&word.as_str()));
+    quote_command(&words)
+}
+
+/// Whether the program of the command line `text` exists, as a path or on `$PATH`
+fn command_exists(text: &str) -> bool {
+    let Some(program) = shlex::split(text).and_then(|tokens| tokens.into_iter().next()) else {
+        return false;
+    };
+    if program.contains('/') {
+        return is_executable(Path::new(&program));
+    }
+    path_dirs()
+        .iter()
+        .any(|dir| is_executable(&dir.join(&program)))
+}
+
+fn path_dirs() -> Vec<PathBuf> {
+    std::env::var_os("PATH")
+        .map(|path| std::env::split_paths(&path).collect())
+        .unwrap_or_default()
+}
+
+fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
@@ -0,0 +1,270 @@
+//! User configuration, read from `$XDG_CONFIG_HOME/rusty-open/config`
+//!
+//! The file uses the key file format, e.g.
+//!
+//! ```ini
+//! [General]
+//! BareHostnames=true
+//! NoPrompt=false
+//! Countdown=5
+//! IconTheme=Papirus
+//!
+//! [Countdown]
+//! image/*=2
+//! x-scheme-handler/https=3
+//! application/x-executable=0
+//!
+//! [Helpers]
+//! Timeout=5
+//!
+//! [HelperTimeouts]
+//! qtxdg-mat=10
+//!
+//! [Overrides]
+//! text/markdown=kate --line 1 %f
+//! x-scheme-handler/https=firefox.desktop
+//! ```
+
+use {
+    crate::keyfile::KeyFile,
+    std::{
+        io::ErrorKind,
+        path::{Path, PathBuf},
+        sync::OnceLock,
+        time::Duration,
+    },
+};
+
+/// Used when neither the environment nor the config file specify a timeout
+const DEFAULT_HELPER_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Config {
+    keyfile: KeyFile,
+}
+
+pub fn path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("rusty-open").join("config"))
+}
+
+/// The configuration, loaded on first access.
+///
+/// A missing or unreadable config file results in the default configuration.
+pub fn get() -> &'static Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    CONFIG.get_or_init(|| Config {
+        keyfile: path()
+            .and_then(|path| KeyFile::load(path).ok())
+            .unwrap_or_default(),
+    })
+}
+
+impl Config {
+    /// How long we wait for helper program `program` (e.g. `xdg-mime`) before giving up on it.
+    ///
+    /// `RUSTY_OPEN_HELPER_TIMEOUT` overrides the config file.
+    pub fn helper_timeout(&self, program: &str) -> Duration {
+        std::env::var("RUSTY_OPEN_HELPER_TIMEOUT")
+            .ok()
+            .and_then(|s| parse_secs(&s))
+            .or_else(|| {
+                self.keyfile
+                    .get("HelperTimeouts", program)
+                    .and_then(parse_secs)
+            })
+            .or_else(|| self.keyfile.get("Helpers", "Timeout").and_then(parse_secs))
+            .unwrap_or(DEFAULT_HELPER_TIMEOUT)
+    }
+
+    /// Whether arguments like `example.com/page` should be opened as `https:` URLs.
+    ///
+    /// `RUSTY_OPEN_BARE_HOSTNAMES` overrides the config file.
+    pub fn bare_hostnames(&self) -> bool {
+        std::env::var("RUSTY_OPEN_BARE_HOSTNAMES")
+            .ok()
+            .and_then(|s| parse_bool(&s))
+            .or_else(|| {
+                self.keyfile
+                    .get("General", "BareHostnames")
+                    .and_then(parse_bool)
+            })
+            .unwrap_or(false)
+    }
+
+    /// Whether to launch the resolved application without asking for confirmation.
+    ///
+    /// `RUSTY_OPEN_NO_PROMPT` overrides the config file.
+    pub fn no_prompt(&self) -> bool {
+        std::env::var("RUSTY_OPEN_NO_PROMPT")
+            .ok()
+            .and_then(|s| parse_bool(&s))
+            .or_else(|| self.keyfile.get("General", "NoPrompt").and_then(parse_bool))
+            .unwrap_or(false)
+    }
+
+    /// How long the confirmation window for `mime` waits before launching by itself, if at all.
+    ///
+    /// `RUSTY_OPEN_COUNTDOWN` overrides the config file, where the type itself wins over its
+    /// media type (`image/*`), which wins over the general setting. Zero turns it off.
+    pub fn countdown(&self, mime: &str) -> Option<Duration> {
+        std::env::var("RUSTY_OPEN_COUNTDOWN")
+            .ok()
+            .and_then(|s| parse_secs(&s))
+            .or_else(|| self.countdown_in_file(mime))
+            .filter(|duration| !duration.is_zero())
+    }
+
+    /// The countdown for `mime` in the config file, which may be zero
+    fn countdown_in_file(&self, mime: &str) -> Option<Duration> {
+        let media = mime.split_once('/').map(|(media, _)| format!("{media}/*"));
+        self.keyfile
+            .get("Countdown", mime)
+            .and_then(parse_secs)
+            .or_else(|| {
+                media
+                    .and_then(|media| self.keyfile.get("Countdown", &media))
+                    .and_then(parse_secs)
+            })
+            .or_else(|| {
+                self.keyfile
+                    .get("General", "Countdown")
+                    .and_then(parse_secs)
+            })
+    }
+
+    /// Icon theme to use instead of the desktop's
+    pub fn icon_theme(&self) -> Option<&str> {
+        self.keyfile
+            .get("General", "IconTheme")
+            .filter(|name| !name.trim().is_empty())
+    }
+
+    /// The application to always open `mime` with, like `--with`
+    pub fn type_override(&self, mime: &str) -> Option<&str> {
+        self.keyfile
+            .get("Overrides", mime)
+            .filter(|with| !with.trim().is_empty())
+    }
+}
+
+/// Save `with` as the application to always open `mime` with, in the config file.
+///
+/// Only the file changes, `get` keeps returning what was loaded at first.
+pub fn save_type_override(mime: &str, with: &str) -> std::io::Result<PathBuf> {
+    let path = path().ok_or(ErrorKind::NotFound)?;
+    save_type_override_to(&path, mime, with)?;
+    Ok(path)
+}
+
+/// Set the override of `mime` in the config file at `path`, keeping everything else in it
+fn save_type_override_to(path: &Path, mime: &str, with: &str) -> std::io::Result<()> {
+    let mut kf = match KeyFile::load(path) {
+        Ok(kf) => kf,
+        Err(err) if err.kind() == ErrorKind::NotFound => KeyFile::default(),
+        Err(err) => return Err(err),
+    };
+    kf.set("Overrides", mime, with);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    kf.save(path)
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    match s.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_secs(s: &str) -> Option<Duration> {
+    s.trim()
+        .parse()
+        .ok()
+        .and_then(|secs: f64| Duration::try_from_secs_f64(secs).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+# rusty-open config, copied from the README
+[General]
+# Launch without asking
+NoPrompt=false
+Countdown=5
+
+[Overrides]
+# Markdown opens at the top
+text/markdown=kate --line 1 %f
+";
+
+    #[test]
+    fn countdown_precedence() {
+        let config = Config {
+            keyfile: KeyFile::parse(
+                "[General]\nCountdown=5\n\n\
+                 [Countdown]\nimage/*=2\nimage/png=3\nvideo/*=0\nvideo/mp4=1\n\
+                 text/*=soon\napplication/x-executable=0\n",
+            ),
+        };
+        for (mime, secs) in [
+            // The type, then its media type, then the general setting
+            ("image/png", Some(3)),
+            ("image/jpeg", Some(2)),
+            ("audio/ogg", Some(5)),
+            ("video/mp4", Some(1)),
+            // Zero is a setting of its own, which doesn't fall through
+            ("video/webm", Some(0)),
+            ("application/x-executable", Some(0)),
+            // Unlike something that isn't a number
+            ("text/plain", Some(5)),
+            ("inode/directory", Some(5)),
+        ] {
+            assert_eq!(
+                config.countdown_in_file(mime),
+                secs.map(Duration::from_secs),
+                "{mime}"
+            );
+        }
+        // Zero means no countdown, once nothing in the environment overrides it
+        if std::env::var_os("RUSTY_OPEN_COUNTDOWN").is_none() {
+            assert_eq!(config.countdown("video/webm"), None);
+            assert_eq!(config.countdown("image/png"), Some(Duration::from_secs(3)));
+        }
+        let unset = Config {
+            keyfile: KeyFile::parse("[Countdown]\nimage/*=2\n"),
+        };
+        assert_eq!(unset.countdown_in_file("text/plain"), None);
+        assert_eq!(
+            unset.countdown_in_file("image/gif"),
+            Some(Duration::from_secs(2))
+        );
+    }
+
+    #[test]
+    fn saving_an_override_keeps_the_rest() {
+        let dir = std::env::temp_dir().join(format!("config-test-{}", std::process::id()));
+        let path = dir.join("rusty-open").join("config");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, CONFIG).unwrap();
+        save_type_override_to(&path, "image/png", "gimp %f").unwrap();
+        save_type_override_to(&path, "text/markdown", "ghostwriter %f").unwrap();
+        let expected = CONFIG.replace("kate --line 1 %f\n", "ghostwriter %f\nimage/png=gimp %f\n");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), expected);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn saving_an_override_creates_the_file() {
+        let dir = std::env::temp_dir().join(format!("config-new-test-{}", std::process::id()));
+        let path = dir.join("rusty-open").join("config");
+        save_type_override_to(&path, "image/png", "gimp.desktop").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "[Overrides]\nimage/png=gimp.desktop\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,54 @@
+//! Launching from the confirmation window by itself after a while, unless the user steps in
+
+use {
+    egui_sf2g::egui,
+    std::time::{Duration, Instant},
+};
+
+pub struct Countdown {
+    started: Instant,
+    duration: Duration,
+}
+
+impl Countdown {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            started: Instant::now(),
+            duration,
+        }
+    }
+
+    /// Whether the user did anything in the window this frame: pressed a key, clicked or
+    /// scrolled.
+    ///
+    /// Escape is consumed, so it only stops the countdown instead of also cancelling.
+    pub fn interrupted(ctx: &egui::Context) -> bool {
+        ctx.input_mut(|inp| {
+            let esc = inp.consume_key(egui::Modifiers::NONE, egui::Key::Escape);
+            esc || inp.events.iter().any(|ev| {
+                matches!(
+                    ev,
+                    egui::Event::Key { pressed: true, .. }
+                        | egui::Event::PointerButton { pressed: true, .. }
+                        | egui::Event::MouseWheel { .. }
+                        | egui::Event::Text(_)
+                )
+            })
+        })
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.started.elapsed() >= self.duration
+    }
+
+    pub fn show(&self, ui: &mut egui::Ui) {
+        let remaining = self.duration.saturating_sub(self.started.elapsed());
+        let fraction = remaining.as_secs_f32() / self.duration.as_secs_f32();
+        ui.add(egui::ProgressBar::new(fraction).text(format!(
+            "Running in {} s, press Escape to stop",
+            remaining.as_secs_f32().ceil()
+        )));
+        // Keep counting down without input
+        ui.ctx().request_repaint();
+    }
+}
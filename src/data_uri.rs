@@ -0,0 +1,96 @@
+//! `data:` URLs (RFC 2397), turned into temporary files that handlers can open
+
+use {
+    crate::{shared_mime, temp_file},
+    data_url::{DataUrl, DataUrlError},
+    std::{io::Write, path::PathBuf},
+    thiserror::Error,
+};
+
+#[derive(Error, Debug)]
+pub enum DataUriError {
+    #[error("Malformed data: URL: {0}")]
+    Syntax(DataUrlError),
+    #[error("Invalid base64 payload")]
+    Base64,
+    #[error("Couldn't write temporary file: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Decode `url` and write its payload to a fresh temporary file.
+///
+/// Returns the path of the file, and the media type declared by the URL
+/// (`text/plain` if it doesn't declare one). With `dry_run`, the payload is only decoded, and
+/// the path is where it would have been written.
+pub fn materialize(url: &str, dry_run: bool) -> Result<(PathBuf, String), DataUriError> {
+    let (body, mime) = decode(url)?;
+    let ext = shared_mime::preferred_extension(&mime);
+    if dry_run {
+        return Ok((temp_file::new_path(ext.as_deref())?, mime));
+    }
+    let (mut file, path) = temp_file::create(ext.as_deref())?;
+    file.write_all(&body)?;
+    Ok((path, mime))
+}
+
+/// The payload of `url`, and its media type without parameters
+fn decode(url: &str) -> Result<(Vec<u8>, String), DataUriError> {
+    let data = DataUrl::process(url).map_err(DataUriError::Syntax)?;
+    let (body, _fragment) = data.decode_to_vec().map_err(|_| DataUriError::Base64)?;
+    let media_type = data.mime_type();
+    Ok((body, format!("{}/{}", media_type.type_, media_type.subtype)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn payloads_are_decoded() {
+        for (url, body, mime) in [
+            (
+                "data:,Hello%2C%20World%21",
+                &b"Hello, World!"[..],
+                "text/plain",
+            ),
+            (
+                "data:text/html,%3Ch1%3EHi%3C%2Fh1%3E",
+                b"<h1>Hi</h1>",
+                "text/html",
+            ),
+            ("data:;base64,SGk=", b"Hi", "text/plain"),
+            (
+                "data:image/png;base64,iVBORw0K",
+                b"\x89PNG\r\n",
+                "image/png",
+            ),
+            // Parameters are dropped, and whitespace in base64 is ignored
+            (
+                "data:text/plain;charset=utf-8;base64,SG k=",
+                b"Hi",
+                "text/plain",
+            ),
+            ("data:Text/CSV,a,b#fragment", b"a,b", "text/csv"),
+            ("DATA:,%ff", b"\xff", "text/plain"),
+        ] {
+            let (decoded, decoded_mime) = decode(url).unwrap();
+            assert_eq!((&decoded[..], decoded_mime.as_str()), (body, mime), "{url}");
+        }
+    }
+
+    #[test]
+    fn malformed_urls_fail() {
+        assert!(matches!(
+            decode("data:text/plain"),
+            Err(DataUriError::Syntax(_))
+        ));
+        assert!(matches!(
+            decode("https://example.com/,a"),
+            Err(DataUriError::Syntax(_))
+        ));
+        assert!(matches!(
+            decode("data:;base64,SGk!"),
+            Err(DataUriError::Base64)
+        ));
+    }
+}
@@ -0,0 +1,286 @@
+//! Composing mail with the `mailto:` handler, for `rusty-open email` and `xdg-email`
+
+use {
+    crate::{Overrides, Status, cli::Email, file_uri, open, trace::Trace},
+    detect_desktop_environment::DesktopEnvironment,
+    percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode},
+    std::{
+        ffi::{OsStr, OsString},
+        path::{Path, PathBuf},
+    },
+    url::Url,
+};
+
+/// Everything but the unreserved characters of RFC 3986
+const VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Addresses keep their `@` and `+` readable
+const ADDRESS: &AsciiSet = &VALUE.remove(b'@').remove(b'+');
+
+/// Mail clients that want attachments in their own way
+enum Client {
+    /// `-compose "to='...',attachment='...'"`
+    Thunderbird,
+    /// `--subject ... --attach ... address`
+    KMail,
+    /// Anything else, including Evolution, gets `attach=` parameters in the `mailto:` URL
+    Generic,
+}
+
+/// Open a composer for `email` in the `mailto:` handler
+pub fn compose(email: Email, de: Option<DesktopEnvironment>, trace: &mut Trace) -> Status {
+    let email = merge_mailto(email);
+    let mut attachments = Vec::new();
+    for path in &email.attach {
+        match std::fs::canonicalize(path) {
+            Ok(path) => attachments.push(path),
+            Err(err) => {
+                return Status::PathError {
+                    path: path.into(),
+                    err,
+                };
+            }
+        }
+    }
+    let url = mailto_url(&email, &attachments);
+    let mut status = open(OsStr::new(&url), de, Overrides::default(), false, trace);
+    if let Status::PromptExec {
+        to_exec,
+        args,
+        appfile_path,
+        ..
+    } = &mut status
+        && let Some(pos) = args.iter().position(|arg| *arg == *url)
+    {
+        let client_args = match Client::detect(to_exec, appfile_path) {
+            Client::Thunderbird => thunderbird_args(&email, &attachments),
+            Client::KMail => kmail_args(&email, &attachments),
+            Client::Generic => return status,
+        };
+        args.splice(pos..=pos, client_args);
+    }
+    status
+}
+
+impl Client {
+    /// Tell the client apart by its program, or by its desktop file (e.g. for Flatpaks)
+    fn detect(to_exec: &str, appfile_path: &Path) -> Self {
+        let names = [Path::new(to_exec).file_name(), appfile_path.file_stem()]
+            .map(|name| name.unwrap_or_default().to_string_lossy().to_lowercase());
+        let is = |needles: &[&str]| {
+            names
+                .iter()
+                .any(|name| needles.iter().any(|needle| name.contains(needle)))
+        };
+        if is(&["thunderbird", "icedove", "betterbird"]) {
+            Self::Thunderbird
+        } else if is(&["kmail"]) {
+            Self::KMail
+        } else {
+            Self::Generic
+        }
+    }
+}
+
+/// Take apart any `mailto:` URLs among the addresses, adding their fields to `email`.
+///
+/// Fields given as options win over those of the URL.
+fn merge_mailto(mut email: Email) -> Email {
+    let mut to = Vec::new();
+    for arg in std::mem::take(&mut email.to) {
+        let url = match Url::parse(&arg) {
+            Ok(url) if url.scheme() == "mailto" => url,
+            _ => {
+                to.push(arg);
+                continue;
+            }
+        };
+        to.extend(split_addresses(&decode(url.path())));
+        // Not `query_pairs`, which would turn the `+` of addresses like `me+tag@...` into spaces
+        let pairs = url.query().unwrap_or_default().split('&');
+        for (key, value) in pairs.filter_map(|pair| pair.split_once('=')) {
+            let value = decode(value);
+            match key.to_ascii_lowercase().as_str() {
+                "to" => to.extend(split_addresses(&value)),
+                "cc" => email.cc.extend(split_addresses(&value)),
+                "bcc" => email.bcc.extend(split_addresses(&value)),
+                "subject" => {
+                    email.subject.get_or_insert(value);
+                }
+                "body" => {
+                    email.body.get_or_insert(value);
+                }
+                "attach" => email.attach.push(OsString::from(
+                    value.strip_prefix("file://").unwrap_or(&value),
+                )),
+                _ => {}
+            }
+        }
+    }
+    email.to = to;
+    email
+}
+
+fn decode(s: &str) -> String {
+    percent_decode_str(s).decode_utf8_lossy().into_owned()
+}
+
+fn split_addresses(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|addr| !addr.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// `text` with CRLF line breaks, as in mail
+fn crlf(text: &str) -> String {
+    text.replace("\r\n", "\n").replace('\n', "\r\n")
+}
+
+/// The `mailto:` URL for `email`, as described in RFC 6068
+fn mailto_url(email: &Email, attachments: &[PathBuf]) -> String {
+    let addresses = |list: &[String]| {
+        list.iter()
+            .map(|addr| utf8_percent_encode(addr, ADDRESS).to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    let mut params = Vec::new();
+    if !email.cc.is_empty() {
+        params.push(format!("cc={}", addresses(&email.cc)));
+    }
+    if !email.bcc.is_empty() {
+        params.push(format!("bcc={}", addresses(&email.bcc)));
+    }
+    if let Some(subject) = &email.subject {
+        params.push(format!("subject={}", utf8_percent_encode(subject, VALUE)));
+    }
+    if let Some(body) = &email.body {
+        params.push(format!("body={}", utf8_percent_encode(&crlf(body), VALUE)));
+    }
+    for path in attachments {
+        params.push(format!(
+            "attach={}",
+            utf8_percent_encode(&file_uri(path), VALUE)
+        ));
+    }
+    let mut url = format!("mailto:{}", addresses(&email.to));
+    if !params.is_empty() {
+        url.push('?');
+        url.push_str(&params.join("&"));
+    }
+    url
+}
+
+/// Arguments for Thunderbird.
+///
+/// Its `-compose` takes a `mailto:` URL, but that can't carry attachments, which need its
+/// `key=value` list instead. As in xdg-email, the values in that list stay percent-encoded,
+/// so quotes and commas in them can't end a field early.
+fn thunderbird_args(email: &Email, attachments: &[PathBuf]) -> Vec<OsString> {
+    if attachments.is_empty() {
+        return vec!["-compose".into(), mailto_url(email, attachments).into()];
+    }
+    let mut fields = Vec::new();
+    for (key, list) in [("to", &email.to), ("cc", &email.cc), ("bcc", &email.bcc)] {
+        if !list.is_empty() {
+            let list: Vec<String> = list
+                .iter()
+                .map(|addr| utf8_percent_encode(addr, ADDRESS).to_string())
+                .collect();
+            fields.push(format!("{key}='{}'", list.join(",")));
+        }
+    }
+    if let Some(subject) = &email.subject {
+        fields.push(format!("subject={}", utf8_percent_encode(subject, VALUE)));
+    }
+    if let Some(body) = &email.body {
+        fields.push(format!("body={}", utf8_percent_encode(&crlf(body), VALUE)));
+    }
+    let uris: Vec<String> = attachments
+        .iter()
+        // Equivalent URIs without the characters that delimit the list
+        .map(|path| file_uri(path).replace('\'', "%27").replace(',', "%2C"))
+        .collect();
+    fields.push(format!("attachment='{}'", uris.join(",")));
+    vec!["-compose".into(), fields.join(",").into()]
+}
+
+fn kmail_args(email: &Email, attachments: &[PathBuf]) -> Vec<OsString> {
+    let mut args: Vec<OsString> = Vec::new();
+    let mut option = |name: &str, value: &OsStr| {
+        args.push(name.into());
+        args.push(value.to_owned());
+    };
+    if let Some(subject) = &email.subject {
+        option("--subject", subject.as_ref());
+    }
+    for cc in &email.cc {
+        option("--cc", cc.as_ref());
+    }
+    for bcc in &email.bcc {
+        option("--bcc", bcc.as_ref());
+    }
+    if let Some(body) = &email.body {
+        option("--body", body.as_ref());
+    }
+    for path in attachments {
+        option("--attach", path.as_os_str());
+    }
+    args.push("--".into());
+    args.extend(email.to.iter().map(OsString::from));
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email() -> Email {
+        Email {
+            to: vec!["a@example.org".into(), "me+tag@example.org".into()],
+            subject: Some("Don't panic, it's fine".into()),
+            body: Some("Hi',attachment='file:///etc/passwd\nBye".into()),
+            ..Email::default()
+        }
+    }
+
+    fn compose_arg(args: &[OsString]) -> &str {
+        assert_eq!(args.len(), 2);
+        assert_eq!(args[0], "-compose");
+        args[1].to_str().unwrap()
+    }
+
+    #[test]
+    fn thunderbird_without_attachments_gets_the_url() {
+        let args = thunderbird_args(&email(), &[]);
+        let url = compose_arg(&args);
+        assert_eq!(
+            url,
+            "mailto:a@example.org,me+tag@example.org\
+             ?subject=Don%27t%20panic%2C%20it%27s%20fine\
+             &body=Hi%27%2Cattachment%3D%27file%3A%2F%2F%2Fetc%2Fpasswd%0D%0ABye"
+        );
+    }
+
+    #[test]
+    fn thunderbird_fields_keep_quotes_and_commas_inside() {
+        let attachments = [PathBuf::from("/tmp/it's, here.txt")];
+        let args = thunderbird_args(&email(), &attachments);
+        let fields = compose_arg(&args);
+        assert_eq!(
+            fields,
+            "to='a@example.org,me+tag@example.org',\
+             subject=Don%27t%20panic%2C%20it%27s%20fine,\
+             body=Hi%27%2Cattachment%3D%27file%3A%2F%2F%2Fetc%2Fpasswd%0D%0ABye,\
+             attachment='file:///tmp/it%27s%2C%20here.txt'"
+        );
+        // Only our own attachment field
+        assert_eq!(fields.matches("attachment='").count(), 1);
+    }
+}
@@ -0,0 +1,142 @@
+//! Printing the resolution of an argument for `--dry-run` and `--explain`
+
+use {
+    crate::{
+        Status,
+        cli::Report,
+        display_os, json,
+        trace::{StepKind, Trace},
+    },
+    std::{ffi::OsStr, fmt::Write},
+};
+
+/// What the report says about the final outcome
+struct Summary<'a> {
+    arg: Option<&'a OsStr>,
+    mime: Option<&'a str>,
+    desktop_file: Option<String>,
+    argv: Option<Vec<String>>,
+    error: Option<String>,
+}
+
+impl<'a> Summary<'a> {
+    fn new(status: &'a Status) -> Self {
+        let mut summary = Self {
+            arg: None,
+            mime: None,
+            desktop_file: None,
+            argv: None,
+            error: status.error_message(),
+        };
+        match status {
+            Status::PromptExec {
+                arg,
+                mime,
+                appfile_path,
+                to_exec,
+                args,
+                ..
+            } => {
+                summary.arg = Some(arg);
+                summary.mime = Some(mime);
+                if !appfile_path.as_os_str().is_empty() {
+                    summary.desktop_file = Some(display_os(appfile_path.as_os_str()));
+                }
+                summary.argv = Some(
+                    std::iter::once(to_exec.clone())
+                        .chain(args.iter().map(|arg| display_os(arg)))
+                        .collect(),
+                );
+            }
+            Status::CouldntDetermineDefault { arg, mime } => {
+                summary.arg = Some(arg);
+                summary.mime = Some(mime);
+            }
+            Status::XdgQueryError { arg, .. } | Status::InvalidTarget { arg, .. } => {
+                summary.arg = Some(arg);
+            }
+            _ => {}
+        }
+        summary
+    }
+}
+
+pub fn print(status: &Status, trace: &Trace, report: Report, json: bool) {
+    let summary = Summary::new(status);
+    if json {
+        println!("{}", to_json(&summary, trace, report));
+    } else {
+        print!("{}", to_text(&summary, trace, report));
+    }
+}
+
+fn to_text(summary: &Summary, trace: &Trace, report: Report) -> String {
+    let mut out = String::new();
+    let mut row = |label: &str, value: &str| {
+        // Writing to a `String` can't fail
+        let _ = writeln!(out, "{label:<14}{value}");
+    };
+    if let Some(arg) = summary.arg {
+        row("Argument:", &display_os(arg));
+    }
+    if let Some(class) = trace.last(StepKind::Classify) {
+        row("Classified as:", class);
+    }
+    if let Some(mime) = summary.mime {
+        row("MIME type:", mime);
+    }
+    if let Some(path) = &summary.desktop_file {
+        row("Desktop file:", path);
+    }
+    if let Some(argv) = &summary.argv {
+        row("Command:", &shell_join(argv));
+    }
+    if let Some(err) = &summary.error {
+        row("Error:", err);
+    }
+    if report == Report::Explain {
+        out.push_str("\nSteps:\n");
+        for step in &trace.steps {
+            let _ = writeln!(out, "  {:<13}{}", step.kind.name(), step.detail);
+            if let Some(source) = &step.source {
+                let _ = writeln!(out, "  {:<13}  from {source}", "");
+            }
+        }
+    }
+    out
+}
+
+fn to_json(summary: &Summary, trace: &Trace, report: Report) -> String {
+    let mut fields = vec![
+        (
+            "argument",
+            json::opt(summary.arg.map(display_os).as_deref()),
+        ),
+        ("classification", json::opt(trace.last(StepKind::Classify))),
+        ("mime", json::opt(summary.mime)),
+        ("desktop_file", json::opt(summary.desktop_file.as_deref())),
+        (
+            "argv",
+            summary.argv.as_ref().map_or_else(
+                || "null".to_string(),
+                |argv| json::array(argv.iter().map(|arg| json::string(arg))),
+            ),
+        ),
+        ("error", json::opt(summary.error.as_deref())),
+    ];
+    if report == Report::Explain {
+        let steps = trace.steps.iter().map(|step| {
+            json::object([
+                ("kind", json::string(step.kind.name())),
+                ("detail", json::string(&step.detail)),
+                ("source", json::opt(step.source.as_deref())),
+            ])
+        });
+        fields.push(("steps", json::array(steps)));
+    }
+    json::object(fields)
+}
+
+fn shell_join(argv: &[String]) -> String {
+    shlex::try_join(argv.iter().map(String::as_str)).unwrap_or_else(|_| argv.join(" "))
+}
@@ -0,0 +1,218 @@
+//! Showing folders and files through the `org.freedesktop.FileManager1` D-Bus interface
+//!
+//! We talk to the session bus with `dbus-send`, so opening a folder is just another command
+//! that can be shown in the prompt and launched like any desktop entry.
+
+use {
+    crate::{helper, trace::Trace},
+    std::{ffi::OsString, path::Path},
+    url::Url,
+};
+
+const BUS_NAME: &str = "org.freedesktop.FileManager1";
+
+#[derive(Clone, Copy)]
+pub enum Method {
+    /// Open the given folders
+    ShowFolders,
+    /// Open the folders containing the given items, with the items selected
+    ShowItems,
+}
+
+impl Method {
+    fn name(self) -> &'static str {
+        match self {
+            Self::ShowFolders => "ShowFolders",
+            Self::ShowItems => "ShowItems",
+        }
+    }
+}
+
+/// The `dbus-send` invocation that calls `method` for the absolute path `path`, if a file
+/// manager is there to answer it.
+///
+/// Returns `None` otherwise, or if `path` can't be expressed as a `file://` URI, leaving it
+/// to the handler of its type.
+pub fn show(method: Method, path: &Path, trace: &mut Trace) -> Option<(String, Vec<OsString>)> {
+    show_with("dbus-send", method, path, trace)
+}
+
+/// [`show`], talking to the bus with the `dbus-send` program at `dbus_send`
+fn show_with(
+    dbus_send: &str,
+    method: Method,
+    path: &Path,
+    trace: &mut Trace,
+) -> Option<(String, Vec<OsString>)> {
+    if !is_available(dbus_send, trace) {
+        return None;
+    }
+    let args = dbus_send_args(method, &[file_uri(path)?]);
+    Some((
+        dbus_send.to_string(),
+        args.into_iter().map(OsString::from).collect(),
+    ))
+}
+
+/// Whether a file manager currently owns, or can be activated for, the FileManager1 bus name
+fn is_available(dbus_send: &str, trace: &mut Trace) -> bool {
+    ["ListNames", "ListActivatableNames"].iter().any(|method| {
+        helper::query(
+            dbus_send,
+            &[
+                "--session",
+                "--print-reply",
+                "--dest=org.freedesktop.DBus",
+                "/org/freedesktop/DBus",
+                format!("org.freedesktop.DBus.{method}").as_str(),
+            ],
+            trace,
+        )
+        .is_ok_and(|reply| reply.contains(&format!("\"{BUS_NAME}\"")))
+    })
+}
+
+/// The `file://` URI of the absolute path `path`, as an item of a `dbus-send` array
+fn file_uri(path: &Path) -> Option<String> {
+    let uri = Url::from_file_path(path).ok()?;
+    // `dbus-send` separates array items with commas
+    Some(uri.as_str().replace(',', "%2C"))
+}
+
+/// Arguments of `dbus-send` calling `method` for `uris`
+fn dbus_send_args(method: Method, uris: &[String]) -> Vec<String> {
+    vec![
+        "--session".to_string(),
+        "--print-reply".to_string(),
+        format!("--dest={BUS_NAME}"),
+        "/org/freedesktop/FileManager1".to_string(),
+        format!("{BUS_NAME}.{}", method.name()),
+        format!("array:string:{}", uris.join(",")),
+        // Startup notification id, which we don't have
+        "string:".to_string(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        std::{
+            ffi::OsStr,
+            os::unix::{ffi::OsStrExt, fs::PermissionsExt},
+            path::PathBuf,
+        },
+    };
+
+    /// A `dbus-send` for a bus on which `owned` names have owners and `activatable` ones can
+    /// be started, replying like the real one does. It fails like without a bus if `exit` isn't
+    /// 0.
+    fn stub_bus(test: &str, owned: &[&str], activatable: &[&str], exit: u8) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("file-manager-{test}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let reply = |names: &[&str]| {
+            let names: String = names
+                .iter()
+                .map(|name| format!("      string \"{name}\"\n"))
+                .collect();
+            format!(
+                "method return time=1.0 sender=org.freedesktop.DBus -> destination=:1.7\n   \
+                 array [\n{names}   ]\n"
+            )
+        };
+        let script = format!(
+            "#!/bin/sh\n\
+             [ {exit} = 0 ] || {{ echo 'Failed to connect to the bus' >&2; exit {exit}; }}\n\
+             case \"$5\" in\n\
+             org.freedesktop.DBus.ListNames) printf '{}' ;;\n\
+             org.freedesktop.DBus.ListActivatableNames) printf '{}' ;;\n\
+             *) exit 1 ;;\n\
+             esac\n",
+            reply(owned),
+            reply(activatable),
+        );
+        let path = dir.join("dbus-send");
+        std::fs::write(&path, script).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    fn show_on(dbus_send: &Path, method: Method, path: &str) -> Option<Vec<String>> {
+        let (program, args) = show_with(
+            dbus_send.to_str().unwrap(),
+            method,
+            Path::new(path),
+            &mut Trace::default(),
+        )?;
+        assert_eq!(program, dbus_send.to_str().unwrap());
+        Some(
+            args.into_iter()
+                .map(|arg| arg.into_string().unwrap())
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn running_file_manager() {
+        let bus = stub_bus("running", &["org.freedesktop.DBus", BUS_NAME], &[], 0);
+        assert_eq!(
+            show_on(&bus, Method::ShowFolders, "/home/me").unwrap(),
+            [
+                "--session",
+                "--print-reply",
+                "--dest=org.freedesktop.FileManager1",
+                "/org/freedesktop/FileManager1",
+                "org.freedesktop.FileManager1.ShowFolders",
+                "array:string:file:///home/me",
+                "string:",
+            ]
+        );
+        std::fs::remove_dir_all(bus.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn activatable_file_manager() {
+        let bus = stub_bus("activatable", &["org.freedesktop.DBus"], &[BUS_NAME], 0);
+        let args = show_on(&bus, Method::ShowItems, "/tmp/a, b.txt").unwrap();
+        assert_eq!(args[4], "org.freedesktop.FileManager1.ShowItems");
+        assert_eq!(args[5], "array:string:file:///tmp/a%2C%20b.txt");
+        std::fs::remove_dir_all(bus.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn falls_back_without_a_file_manager() {
+        // Names that only start with the bus name don't count
+        let other = "org.freedesktop.FileManager1.Extension";
+        let bus = stub_bus("none", &["org.freedesktop.DBus", other], &[other], 0);
+        assert_eq!(show_on(&bus, Method::ShowFolders, "/home/me"), None);
+        let no_bus = stub_bus("no-bus", &[BUS_NAME], &[BUS_NAME], 1);
+        assert_eq!(show_on(&no_bus, Method::ShowFolders, "/home/me"), None);
+        for bus in [bus, no_bus] {
+            std::fs::remove_dir_all(bus.parent().unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn show_items_sends_every_uri() {
+        let uris = ["file:///tmp/a.txt".into(), "file:///tmp/b.txt".into()];
+        let args = dbus_send_args(Method::ShowItems, &uris);
+        assert_eq!(args[4], "org.freedesktop.FileManager1.ShowItems");
+        assert_eq!(args[5], "array:string:file:///tmp/a.txt,file:///tmp/b.txt");
+        assert_eq!(args[6], "string:");
+    }
+
+    #[test]
+    fn uris_are_percent_encoded() {
+        for (path, uri) in [
+            ("/home/me/My Files", "file:///home/me/My%20Files"),
+            ("/tmp/été", "file:///tmp/%C3%A9t%C3%A9"),
+            ("/tmp/a,b", "file:///tmp/a%2Cb"),
+            ("/tmp/100%#1?", "file:///tmp/100%25%231%3F"),
+        ] {
+            assert_eq!(file_uri(Path::new(path)).as_deref(), Some(uri), "{path}");
+        }
+        let latin1 = Path::new(OsStr::from_bytes(b"/tmp/caf\xe9"));
+        assert_eq!(file_uri(latin1).as_deref(), Some("file:///tmp/caf%E9"));
+        assert_eq!(file_uri(Path::new("relative")), None);
+    }
+}
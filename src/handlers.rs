@@ -0,0 +1,130 @@
+//! Listing every application that can open a type, for `rusty-open handlers`
+
+use {
+    crate::{
+        cli, config, json,
+        mimeapps::{self, Handler},
+        resolve,
+        trace::Trace,
+        with_as_desktop_id,
+        xdg_desktop_file::localized,
+    },
+    detect_desktop_environment::DesktopEnvironment,
+    std::{ffi::OsStr, fmt::Write, path::Path},
+};
+
+/// Print the handlers for `query`, which is a MIME type, or anything `rusty-open` can open.
+///
+/// Returns the exit code.
+pub fn run(query: &OsStr, de: Option<DesktopEnvironment>, json: bool, trace: &mut Trace) -> i32 {
+    let mime = match as_mime(query) {
+        Some(mime) => mime.to_string(),
+        // Only the type is needed, so `data:` URLs aren't written out
+        None => match resolve(query, de, None, true, trace) {
+            Ok(resolved) => resolved.mime,
+            Err(status) => {
+                eprintln!("rusty-open: {}", status.error_message().unwrap_or_default());
+                return status.exit_code();
+            }
+        },
+    };
+    let handlers = mimeapps::handlers(&mime);
+    // The same default `open` uses. Not finding one is fine, the list is still useful.
+    let type_override = config::get().type_override(&mime);
+    let default = match type_override {
+        // A command line overriding the default isn't in the list
+        Some(with) => with_as_desktop_id(with),
+        None => mimeapps::effective_default(&mime),
+    };
+    let is_default = |handler: &Handler| default.as_deref() == Some(handler.id.as_str());
+    if json {
+        println!("{}", to_json(&mime, type_override, &handlers, is_default));
+    } else {
+        print!("{}", to_text(&mime, type_override, &handlers, is_default));
+    }
+    if handlers.is_empty() {
+        cli::EXIT_NOT_FOUND
+    } else {
+        0
+    }
+}
+
+/// `query` itself, if it's spelled like a MIME type (`type/subtype`) and isn't an existing path
+fn as_mime(query: &OsStr) -> Option<&str> {
+    let text = query.to_str()?;
+    let (ty, subtype) = text.split_once('/')?;
+    let is_token = |s: &str| {
+        !s.is_empty()
+            && s.bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"!#$&^_.+-".contains(&b))
+    };
+    (is_token(ty) && is_token(subtype) && Path::new(query).symlink_metadata().is_err())
+        .then_some(text)
+}
+
+fn to_text(
+    mime: &str,
+    type_override: Option<&str>,
+    handlers: &[Handler],
+    is_default: impl Fn(&Handler) -> bool,
+) -> String {
+    let mut out = String::new();
+    // Writing to a `String` can't fail
+    let _ = writeln!(out, "MIME type: {mime}");
+    if let Some(with) = type_override {
+        let _ = writeln!(out, "Overridden by [Overrides] in the config file: {with}");
+    }
+    if handlers.is_empty() {
+        out.push_str("No applications found\n");
+        return out;
+    }
+    let width = handlers.iter().map(|h| h.id.len()).max().unwrap_or(0);
+    for handler in handlers {
+        let marker = if is_default(handler) { '*' } else { ' ' };
+        let name = localized(&handler.desktop_map, "Name").unwrap_or_default();
+        let _ = writeln!(out, "{marker} {:<width$}  {name}", handler.id);
+        let _ = write!(
+            out,
+            "    {} in {}",
+            handler.source.kind(),
+            handler.source.path().display()
+        );
+        match &handler.via {
+            Some(parent) => {
+                let _ = writeln!(out, " (for parent type {parent})");
+            }
+            None => out.push('\n'),
+        }
+    }
+    out
+}
+
+fn to_json(
+    mime: &str,
+    type_override: Option<&str>,
+    handlers: &[Handler],
+    is_default: impl Fn(&Handler) -> bool,
+) -> String {
+    let handlers = handlers.iter().map(|handler| {
+        json::object([
+            ("id", json::string(&handler.id)),
+            ("name", json::opt(localized(&handler.desktop_map, "Name"))),
+            ("default", is_default(handler).to_string()),
+            (
+                "desktop_file",
+                json::string(&handler.desktop_file.display().to_string()),
+            ),
+            ("source", json::string(handler.source.kind())),
+            (
+                "source_file",
+                json::string(&handler.source.path().display().to_string()),
+            ),
+            ("via", json::opt(handler.via.as_deref())),
+        ])
+    });
+    json::object([
+        ("mime", json::string(mime)),
+        ("override", json::opt(type_override)),
+        ("handlers", json::array(handlers)),
+    ])
+}
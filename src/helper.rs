@@ -0,0 +1,182 @@
+//! Running external helper programs (`xdg-mime`, `qtxdg-mat`, ...) and collecting their answer
+
+use {
+    crate::{
+        XdgQueryError, config,
+        trace::{StepKind, Trace},
+    },
+    std::{
+        ffi::OsStr,
+        io::Read,
+        process::{Command, Stdio},
+        sync::mpsc::{self, Receiver},
+        thread,
+        time::{Duration, Instant},
+    },
+};
+
+/// Run `program` with `args`, and return its trimmed standard output.
+///
+/// Fails if the program can't be spawned, exits unsuccessfully, doesn't finish within its
+/// configured timeout, or prints nothing.
+pub fn query<S: AsRef<OsStr>>(
+    program: &str,
+    args: &[S],
+    trace: &mut Trace,
+) -> Result<String, XdgQueryError> {
+    let cmd = command_line(program, args);
+    let timeout = config::get().helper_timeout(program);
+    let result = run(program, args, cmd.clone(), timeout);
+    let detail = match &result {
+        Ok(out) if out.lines().nth(1).is_some() => {
+            format!("{} lines of output", out.lines().count())
+        }
+        Ok(out) => out.clone(),
+        Err(err) => err.to_string(),
+    };
+    trace.push(StepKind::Helper, detail, Some(cmd));
+    result
+}
+
+fn run<S: AsRef<OsStr>>(
+    program: &str,
+    args: &[S],
+    cmd: String,
+    timeout: Duration,
+) -> Result<String, XdgQueryError> {
+    let mut child = match Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => return Err(XdgQueryError::Spawn { cmd, err }),
+    };
+    // Read the pipes on separate threads, so a chatty helper can't block on a full pipe
+    let stdout = child.stdout.take().map(read_in_background);
+    let stderr = child.stderr.take().map(read_in_background);
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) => {
+                if Instant::now() >= deadline {
+                    // The helper might already be gone by now, nothing to do about errors here
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return Err(XdgQueryError::Timeout { cmd, timeout });
+                }
+                thread::sleep(Duration::from_millis(5));
+            }
+            Err(err) => return Err(XdgQueryError::Spawn { cmd, err }),
+        }
+    };
+    // A process the helper left behind can keep the pipes open after it exits, so the reads
+    // are bounded by the deadline too
+    let collect = |output: Option<Receiver<Vec<u8>>>| match output {
+        Some(output) => output.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+        None => Ok(Vec::new()),
+    };
+    let Ok(out) = collect(stdout) else {
+        return Err(XdgQueryError::Timeout { cmd, timeout });
+    };
+    if !status.success() {
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).trim().to_string();
+        return Err(XdgQueryError::Failed {
+            cmd,
+            status,
+            stdout: text(&out),
+            // Only for diagnostics, so whatever arrived in time will do
+            stderr: text(&collect(stderr).unwrap_or_default()),
+        });
+    }
+    match std::str::from_utf8(&out) {
+        Ok(s) => {
+            let trimmed = s.trim();
+            if trimmed.is_empty() {
+                Err(XdgQueryError::Empty)
+            } else {
+                Ok(trimmed.to_string())
+            }
+        }
+        Err(e) => Err(XdgQueryError::InvalidUtf8(e)),
+    }
+}
+
+/// Read `src` to its end on a thread of its own, which sends what it read when done
+fn read_in_background(mut src: impl Read + Send + 'static) -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buf = Vec::new();
+        // Whatever we managed to read is still useful for diagnostics
+        let _ = src.read_to_end(&mut buf);
+        // Nobody is waiting anymore if the deadline passed
+        let _ = sender.send(buf);
+    });
+    receiver
+}
+
+/// Shell-quoted rendition of a command line, for diagnostics
+fn command_line<S: AsRef<OsStr>>(program: &str, args: &[S]) -> String {
+    let words: Vec<String> = std::iter::once(program.to_string())
+        .chain(
+            args.iter()
+                .map(|arg| arg.as_ref().to_string_lossy().into_owned()),
+        )
+        .collect();
+    shlex::try_join(words.iter().map(String::as_str)).unwrap_or_else(|_| words.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sh(script: &str, timeout: Duration) -> Result<String, XdgQueryError> {
+        run("sh", &["-c", script], script.to_string(), timeout)
+    }
+
+    #[test]
+    fn output_is_trimmed() {
+        assert_eq!(
+            sh("echo '  text/plain '", Duration::from_secs(5)).unwrap(),
+            "text/plain"
+        );
+        assert!(matches!(
+            sh("true", Duration::from_secs(5)),
+            Err(XdgQueryError::Empty)
+        ));
+    }
+
+    #[test]
+    fn failures_keep_the_output() {
+        let Err(XdgQueryError::Failed { stdout, stderr, .. }) =
+            sh("echo out; echo err >&2; exit 3", Duration::from_secs(5))
+        else {
+            panic!("expected a failure");
+        };
+        assert_eq!((stdout.as_str(), stderr.as_str()), ("out", "err"));
+    }
+
+    #[test]
+    fn slow_helpers_time_out() {
+        let start = Instant::now();
+        assert!(matches!(
+            sh("sleep 10", Duration::from_millis(200)),
+            Err(XdgQueryError::Timeout { .. })
+        ));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn pipes_held_open_by_a_leftover_process_time_out() {
+        let start = Instant::now();
+        // The helper exits right away, but the background `sleep` still has its stdout
+        assert!(matches!(
+            sh("echo answer; sleep 10 &", Duration::from_millis(200)),
+            Err(XdgQueryError::Timeout { .. })
+        ));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
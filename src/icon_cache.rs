@@ -0,0 +1,220 @@
+//! Reader for the `icon-theme.cache` files `gtk-update-icon-cache` writes into icon themes
+//!
+//! The header holds the version, then the offsets of a hash table of icon names and of the
+//! list of directories. Each icon in the hash table points to a list of the directories it's
+//! in, with flags telling which file extensions it has there. Lookups walk one hash chain,
+//! without reading the rest of the file.
+
+use {
+    crate::cache_file::{CacheFile, offset},
+    std::path::Path,
+};
+
+/// Image flags for the file extensions, in the order they're preferred
+const EXTENSIONS: [(u16, &str); 3] = [(4, "png"), (2, "svg"), (1, "xpm")];
+
+/// End of a hash chain
+const NONE: u32 = u32::MAX;
+
+pub struct IconCache {
+    data: CacheFile,
+}
+
+impl IconCache {
+    /// The cache of the theme directory `dir`, if it has one that's no older than the directory.
+    ///
+    /// Like GTK, we only compare with the directory itself, which changes whenever a
+    /// subdirectory is added or removed.
+    pub fn open(dir: &Path) -> Option<Self> {
+        let path = dir.join("icon-theme.cache");
+        let cache_time = path.metadata().ok()?.modified().ok()?;
+        let dir_time = dir.metadata().ok()?.modified().ok()?;
+        if cache_time < dir_time {
+            return None;
+        }
+        let cache = Self {
+            data: CacheFile::read(&path)?,
+        };
+        // Only version 1.x exists
+        (cache.data.u16_at(0)? == 1).then_some(cache)
+    }
+
+    /// The subdirectories icon `name` is in, each with the extension of its file there
+    pub fn lookup(&self, name: &str) -> Vec<(&str, &'static str)> {
+        self.lookup_images(name).unwrap_or_default()
+    }
+
+    fn lookup_images(&self, name: &str) -> Option<Vec<(&str, &'static str)>> {
+        let hash_offset = self.data.u32_at(4)?;
+        let buckets = self.data.u32_at(hash_offset)?;
+        if buckets == 0 {
+            return None;
+        }
+        let mut icon = self
+            .data
+            .u32_at(offset(hash_offset, 4, hash(name) % buckets, 4)?)?;
+        // A chain can't be longer than the number of icons that fit, unless it has a cycle
+        let mut steps = self.data.len() / 12;
+        while icon != NONE {
+            steps = steps.checked_sub(1)?;
+            if self.data.str_at(self.data.u32_at(icon.checked_add(4)?)?)? == name {
+                return self.images(self.data.u32_at(icon.checked_add(8)?)?);
+            }
+            icon = self.data.u32_at(icon)?;
+        }
+        None
+    }
+
+    /// The directories and extensions in the image list at `list`. Each image is the index of
+    /// its directory, its flags, and the offset of image data we don't use.
+    fn images(&self, list: u32) -> Option<Vec<(&str, &'static str)>> {
+        let dir_list = self.data.u32_at(8)?;
+        let count = self
+            .data
+            .entries(list.checked_add(4)?, self.data.u32_at(list)?, 8);
+        let mut images = Vec::new();
+        for i in 0..count {
+            let image = offset(list, 4, i, 8)?;
+            let dir_index = u32::from(self.data.u16_at(image)?);
+            let flags = self.data.u16_at(image.checked_add(2)?)?;
+            let dir = self
+                .data
+                .str_at(self.data.u32_at(offset(dir_list, 4, dir_index, 4)?)?)?;
+            if let Some((_, ext)) = EXTENSIONS.iter().find(|(flag, _)| flags & flag != 0) {
+                images.push((dir, *ext));
+            }
+        }
+        Some(images)
+    }
+}
+
+/// GLib's string hash as used by the cache, which works on signed chars
+fn hash(name: &str) -> u32 {
+    let mut bytes = name.bytes().map(|b| b as i8 as u32);
+    let Some(first) = bytes.next() else {
+        return 0;
+    };
+    bytes.fold(first, |h, b| (h << 5).wrapping_sub(h).wrapping_add(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A cache of `icons`, each with the directory indices and flags of its images, hashed into
+    /// `buckets` chains the way `gtk-update-icon-cache` does
+    fn fixture(buckets: u32, dirs: &[&str], icons: &[(&str, &[(u16, u16)])]) -> Vec<u8> {
+        let mut data = vec![0, 1, 0, 0, 0, 0, 0, 12, 0, 0, 0, 0];
+        let push = |data: &mut Vec<u8>, value: u32| data.extend(value.to_be_bytes());
+        let string = |data: &mut Vec<u8>, s: &str| {
+            let at = data.len() as u32;
+            data.extend(s.as_bytes());
+            data.push(0);
+            at
+        };
+        let mut chains = vec![NONE; buckets as usize];
+        for (name, images) in icons {
+            let name_at = string(&mut data, name);
+            let list = data.len() as u32;
+            push(&mut data, images.len() as u32);
+            for &(dir, flags) in *images {
+                data.extend(dir.to_be_bytes());
+                data.extend(flags.to_be_bytes());
+                push(&mut data, 0);
+            }
+            // Prepended to its chain
+            let bucket = &mut chains[(hash(name) % buckets) as usize];
+            let icon = data.len() as u32;
+            for value in [*bucket, name_at, list] {
+                push(&mut data, value);
+            }
+            *bucket = icon;
+        }
+        let table = data.len() as u32;
+        push(&mut data, buckets);
+        for chain in chains {
+            push(&mut data, chain);
+        }
+        let names: Vec<u32> = dirs.iter().map(|dir| string(&mut data, dir)).collect();
+        let dir_list = data.len() as u32;
+        push(&mut data, names.len() as u32);
+        for name in names {
+            push(&mut data, name);
+        }
+        data[4..8].copy_from_slice(&table.to_be_bytes());
+        data[8..12].copy_from_slice(&dir_list.to_be_bytes());
+        data
+    }
+
+    fn theme() -> Vec<u8> {
+        fixture(
+            2,
+            &["48x48/apps", "scalable/places", "16x16/mimetypes"],
+            &[
+                ("folder", &[(0, 4 | 2), (1, 2)]),
+                ("text-x-generic", &[(2, 1)]),
+                // Only has data embedded in the cache, which we don't use
+                ("emblem", &[(0, 8)]),
+                ("edit-copy", &[(0, 4)]),
+            ],
+        )
+    }
+
+    fn cache(data: Vec<u8>) -> IconCache {
+        IconCache {
+            data: CacheFile::new(data),
+        }
+    }
+
+    #[test]
+    fn hash_matches_gtk() {
+        // `icon_name_hash` of gtk-update-icon-cache, which adds the bytes as signed chars
+        for (name, hash_value) in [
+            ("", 0),
+            ("a", 97),
+            ("folder", 3026001006),
+            ("text-x-generic", 870467810),
+            ("application-x-executable-symbolic", 3025037635),
+            ("café", 94414350),
+        ] {
+            assert_eq!(hash(name), hash_value, "hash of {name:?}");
+        }
+    }
+
+    #[test]
+    fn lookup_follows_chains() {
+        let cache = cache(theme());
+        assert_eq!(
+            cache.lookup("folder"),
+            [("48x48/apps", "png"), ("scalable/places", "svg")]
+        );
+        assert_eq!(cache.lookup("text-x-generic"), [("16x16/mimetypes", "xpm")]);
+        assert_eq!(cache.lookup("edit-copy"), [("48x48/apps", "png")]);
+        assert!(cache.lookup("emblem").is_empty());
+        assert!(cache.lookup("missing").is_empty());
+    }
+
+    #[test]
+    fn chains_with_a_cycle_end() {
+        let mut data = fixture(1, &["apps"], &[("a", &[(0, 4)]), ("b", &[(0, 4)])]);
+        let read =
+            |data: &[u8], at: usize| u32::from_be_bytes(data[at..at + 4].try_into().unwrap());
+        // "b" heads the only chain and leads to "a", which we point back to "b"
+        let b = read(&data, read(&data, 4) as usize + 4);
+        let a = read(&data, b as usize) as usize;
+        data[a..a + 4].copy_from_slice(&b.to_be_bytes());
+        let cache = cache(data);
+        assert_eq!(cache.lookup("a"), [("apps", "png")]);
+        assert!(cache.lookup("missing").is_empty());
+    }
+
+    #[test]
+    fn damaged_caches_dont_panic() {
+        crate::cache_file::for_each_damaged(&theme(), |data| {
+            let cache = IconCache { data };
+            for name in ["folder", "text-x-generic", "edit-copy"] {
+                cache.lookup(name);
+            }
+        });
+    }
+}
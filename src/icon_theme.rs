@@ -0,0 +1,314 @@
+//! Finding out which icon theme the desktop uses
+
+use {
+    crate::{basedir, config, keyfile::KeyFile, settings},
+    std::path::PathBuf,
+    x11rb::protocol::xproto::{AtomEnum, ConnectionExt},
+};
+
+/// The theme every other theme falls back to, as required by the icon theme spec
+pub const FALLBACK: &str = "hicolor";
+
+/// Directories icon themes are looked up in, most important first
+pub fn base_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = dirs::home_dir()
+        .map(|home| home.join(".icons"))
+        .into_iter()
+        .collect();
+    dirs.extend(
+        basedir::data_dirs()
+            .into_iter()
+            .map(|dir| dir.join("icons")),
+    );
+    dirs
+}
+
+/// Name of the icon theme to look icons up in.
+///
+/// The first installed theme from the config file, the desktop's settings or XSettings,
+/// falling back to `hicolor`.
+pub fn current() -> String {
+    let candidates: [fn() -> Option<String>; 5] = [
+        || config::get().icon_theme().map(str::to_string),
+        kde_theme,
+        gsettings_theme,
+        gtk_theme,
+        xsettings_theme,
+    ];
+    candidates
+        .iter()
+        .filter_map(|candidate| candidate())
+        .find(|name| is_installed(name))
+        .unwrap_or_else(|| FALLBACK.to_string())
+}
+
+/// Whether theme `name` is in one of the base directories
+fn is_installed(name: &str) -> bool {
+    base_dirs()
+        .iter()
+        .any(|dir| dir.join(name).join("index.theme").is_file())
+}
+
+/// `Theme` in the `Icons` group of `kdeglobals`, on Plasma
+fn kde_theme() -> Option<String> {
+    if !settings::is_kde() {
+        return None;
+    }
+    first_value(&paths("kdeglobals"), "Icons", "Theme")
+}
+
+/// `icon-theme` of `org.gnome.desktop.interface`, if GSettings uses its key file backend.
+///
+/// Only the quotes around the GVariant string are stripped, escapes like `\'` inside are
+/// left as they are, since theme names are directory names that don't need them.
+fn gsettings_theme() -> Option<String> {
+    let path = dirs::config_dir()?.join("glib-2.0/settings/keyfile");
+    let value = KeyFile::load(path)
+        .ok()?
+        .get("org/gnome/desktop/interface", "icon-theme")?
+        .to_string();
+    // Values are GVariant text, so strings are quoted
+    let name = value
+        .strip_prefix('\'')
+        .and_then(|v| v.strip_suffix('\''))
+        .unwrap_or(&value);
+    non_empty(name)
+}
+
+/// `gtk-icon-theme-name` of the GTK 4 or GTK 3 `settings.ini`
+fn gtk_theme() -> Option<String> {
+    let mut files = paths("gtk-4.0/settings.ini");
+    files.extend(paths("gtk-3.0/settings.ini"));
+    files.push(PathBuf::from("/etc/gtk-3.0/settings.ini"));
+    first_value(&files, "Settings", "gtk-icon-theme-name")
+}
+
+/// `Net/IconThemeName` from the XSettings manager, e.g. `xsettingsd` or `gsd-xsettings`
+fn xsettings_theme() -> Option<String> {
+    let (conn, screen) = x11rb::connect(None).ok()?;
+    let atom = |name: &str| {
+        conn.intern_atom(false, name.as_bytes())
+            .ok()?
+            .reply()
+            .ok()
+            .map(|reply| reply.atom)
+    };
+    let selection = atom(&format!("_XSETTINGS_S{screen}"))?;
+    let settings = atom("_XSETTINGS_SETTINGS")?;
+    let owner = conn
+        .get_selection_owner(selection)
+        .ok()?
+        .reply()
+        .ok()?
+        .owner;
+    if owner == x11rb::NONE {
+        return None;
+    }
+    let data = conn
+        .get_property(false, owner, settings, AtomEnum::ANY, 0, u32::MAX / 4)
+        .ok()?
+        .reply()
+        .ok()?
+        .value;
+    xsettings_string(&data, "Net/IconThemeName")
+}
+
+/// The string setting `wanted` of an `_XSETTINGS_SETTINGS` property, as described in the
+/// XSettings spec
+fn xsettings_string(data: &[u8], wanted: &str) -> Option<String> {
+    let big_endian = *data.first()? == 1;
+    let u16_at = |pos: usize| {
+        let bytes = data.get(pos..pos + 2)?.try_into().ok()?;
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        } as usize)
+    };
+    let u32_at = |pos: usize| {
+        let bytes = data.get(pos..pos + 4)?.try_into().ok()?;
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        } as usize)
+    };
+    let padded = |len: usize| len.div_ceil(4) * 4;
+    let count = u32_at(8)?;
+    let mut pos = 12;
+    for _ in 0..count {
+        let kind = *data.get(pos)?;
+        let name_len = u16_at(pos + 2)?;
+        let name = data.get(pos + 4..pos + 4 + name_len)?;
+        // Past the name and the serial of the last change
+        pos += 4 + padded(name_len) + 4;
+        match kind {
+            // Integer
+            0 => pos += 4,
+            // String
+            1 => {
+                let len = u32_at(pos)?;
+                let value = data.get(pos + 4..pos + 4 + len)?;
+                if name == wanted.as_bytes() {
+                    return non_empty(std::str::from_utf8(value).ok()?);
+                }
+                pos += 4 + padded(len);
+            }
+            // Color
+            2 => pos += 8,
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// `file` in every config directory, most important first
+fn paths(file: &str) -> Vec<PathBuf> {
+    basedir::config_dirs()
+        .into_iter()
+        .map(|dir| dir.join(file))
+        .collect()
+}
+
+/// The first non-empty value of `key` in `group` among `files`
+fn first_value(files: &[PathBuf], group: &str, key: &str) -> Option<String> {
+    files
+        .iter()
+        .filter_map(|path| KeyFile::load(path).ok())
+        .find_map(|kf| kf.get(group, key).and_then(non_empty))
+}
+
+fn non_empty(name: &str) -> Option<String> {
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    enum Setting<'a> {
+        Integer(&'a str, i32),
+        String(&'a str, &'a [u8]),
+        Color(&'a str),
+    }
+
+    /// An `_XSETTINGS_SETTINGS` property holding `settings`
+    fn property(big_endian: bool, settings: &[Setting]) -> Vec<u8> {
+        let u16_bytes = |n: u16| {
+            if big_endian {
+                n.to_be_bytes()
+            } else {
+                n.to_le_bytes()
+            }
+        };
+        let u32_bytes = |n: u32| {
+            if big_endian {
+                n.to_be_bytes()
+            } else {
+                n.to_le_bytes()
+            }
+        };
+        let pad = |data: &mut Vec<u8>| data.resize(data.len().div_ceil(4) * 4, 0);
+        let mut data = vec![u8::from(big_endian), 0, 0, 0];
+        data.extend(u32_bytes(7));
+        data.extend(u32_bytes(settings.len() as u32));
+        for setting in settings {
+            let (kind, name) = match setting {
+                Setting::Integer(name, _) => (0, name),
+                Setting::String(name, _) => (1, name),
+                Setting::Color(name) => (2, name),
+            };
+            data.extend([kind, 0]);
+            data.extend(u16_bytes(name.len() as u16));
+            data.extend(name.as_bytes());
+            pad(&mut data);
+            data.extend(u32_bytes(3));
+            match setting {
+                Setting::Integer(_, value) => data.extend(u32_bytes(*value as u32)),
+                Setting::String(_, value) => {
+                    data.extend(u32_bytes(value.len() as u32));
+                    data.extend(*value);
+                    pad(&mut data);
+                }
+                Setting::Color(_) => {
+                    for channel in [1, 2, 3, 0xffff] {
+                        data.extend(u16_bytes(channel));
+                    }
+                }
+            }
+        }
+        data
+    }
+
+    fn settings() -> [Setting<'static>; 5] {
+        [
+            Setting::Integer("Net/DoubleClickTime", 400),
+            Setting::Color("Gtk/Color"),
+            // Names and values that need no padding, and one that needs 3 bytes
+            Setting::String("Net/ThemeName", b"Adwaita"),
+            Setting::String("Gtk/FontName", b"Cantarell 11"),
+            Setting::String("Net/IconThemeName", b"Papirus"),
+        ]
+    }
+
+    #[test]
+    fn strings_of_either_byte_order() {
+        for big_endian in [false, true] {
+            let data = property(big_endian, &settings());
+            for (name, value) in [
+                ("Net/IconThemeName", "Papirus"),
+                ("Net/ThemeName", "Adwaita"),
+                ("Gtk/FontName", "Cantarell 11"),
+            ] {
+                assert_eq!(xsettings_string(&data, name).as_deref(), Some(value));
+            }
+            // Not strings, or not there
+            assert_eq!(xsettings_string(&data, "Net/DoubleClickTime"), None);
+            assert_eq!(xsettings_string(&data, "Gtk/Color"), None);
+            assert_eq!(xsettings_string(&data, "Net/Icon"), None);
+        }
+    }
+
+    #[test]
+    fn unusable_values() {
+        let data = property(false, &[Setting::String("Net/IconThemeName", b"  ")]);
+        assert_eq!(xsettings_string(&data, "Net/IconThemeName"), None);
+        let data = property(true, &[Setting::String("Net/IconThemeName", b"caf\xe9")]);
+        assert_eq!(xsettings_string(&data, "Net/IconThemeName"), None);
+    }
+
+    #[test]
+    fn unknown_types_stop_the_search() {
+        let mut data = property(false, &settings());
+        // The type of the integer setting
+        data[12] = 3;
+        assert_eq!(xsettings_string(&data, "Net/IconThemeName"), None);
+    }
+
+    #[test]
+    fn truncated_data() {
+        for big_endian in [false, true] {
+            let data = property(big_endian, &settings());
+            // The icon theme is the last setting, and its value ends the data but for a byte of
+            // padding, which isn't needed
+            for len in 0..data.len() - 1 {
+                assert_eq!(
+                    xsettings_string(&data[..len], "Net/IconThemeName"),
+                    None,
+                    "{len}"
+                );
+            }
+            assert_eq!(
+                xsettings_string(&data[..data.len() - 1], "Net/IconThemeName").as_deref(),
+                Some("Papirus")
+            );
+            // More settings than there are
+            let mut data = data;
+            let count = if big_endian { 11 } else { 8 };
+            data[count] = 6;
+            assert_eq!(xsettings_string(&data, "Net/Missing"), None);
+        }
+        assert_eq!(xsettings_string(&[], "Net/IconThemeName"), None);
+    }
+}
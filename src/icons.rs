@@ -0,0 +1,238 @@
+//! Icon lookup as described in the icon theme spec, in the current theme and the themes it
+//! inherits from, using `icon-theme.cache` files where they are up to date
+
+use {
+    crate::{basedir, icon_cache::IconCache, icon_theme, keyfile::KeyFile},
+    std::{
+        collections::HashSet,
+        path::{Path, PathBuf},
+        sync::OnceLock,
+    },
+};
+
+/// File extensions of icons, in the order they're preferred
+const EXTENSIONS: [&str; 3] = ["png", "svg", "xpm"];
+
+pub struct Icons {
+    /// The current theme, then the themes it inherits from, depth first, then `hicolor`
+    themes: Vec<Theme>,
+}
+
+struct Theme {
+    /// The theme's directory in each base directory, with its cache if it's up to date
+    roots: Vec<(PathBuf, Option<IconCache>)>,
+    dirs: Vec<Directory>,
+}
+
+/// A subdirectory listed in `index.theme`
+struct Directory {
+    path: String,
+    /// Range of sizes in pixels the icons can be shown at, scale included
+    min: u32,
+    max: u32,
+}
+
+/// The icons of the current theme, loaded on first access
+pub fn get() -> &'static Icons {
+    static ICONS: OnceLock<Icons> = OnceLock::new();
+    ICONS.get_or_init(|| {
+        let mut icons = Icons { themes: Vec::new() };
+        let mut seen = HashSet::new();
+        icons.add_theme(&icon_theme::current(), &mut seen);
+        icons.add_theme(icon_theme::FALLBACK, &mut seen);
+        icons
+    })
+}
+
+impl Icons {
+    /// Add theme `name`, followed by the themes it inherits from
+    fn add_theme(&mut self, name: &str, seen: &mut HashSet<String>) {
+        if !seen.insert(name.to_string()) {
+            return;
+        }
+        let Some((theme, inherits)) = Theme::load(name) else {
+            return;
+        };
+        self.themes.push(theme);
+        for parent in inherits {
+            self.add_theme(&parent, seen);
+        }
+    }
+
+    /// Path of the icon `name` closest to `size` pixels.
+    ///
+    /// `name` may also be an absolute path, as allowed for the `Icon` key of desktop entries.
+    pub fn find(&self, name: &str, size: u32) -> Option<PathBuf> {
+        if Path::new(name).is_absolute() {
+            return Path::new(name).is_file().then(|| PathBuf::from(name));
+        }
+        if name.is_empty() {
+            return None;
+        }
+        self.themes
+            .iter()
+            .find_map(|theme| theme.find(name, size))
+            .or_else(|| find_unthemed(name))
+    }
+}
+
+impl Theme {
+    /// Theme `name` along with the names of the themes it inherits from
+    fn load(name: &str) -> Option<(Self, Vec<String>)> {
+        let roots: Vec<PathBuf> = icon_theme::base_dirs()
+            .into_iter()
+            .map(|dir| dir.join(name))
+            .filter(|dir| dir.is_dir())
+            .collect();
+        let index = roots
+            .iter()
+            .find_map(|root| KeyFile::load(root.join("index.theme")).ok())?;
+        let list = |key: &str| -> Vec<String> {
+            index
+                .get("Icon Theme", key)
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+        let mut paths = list("Directories");
+        paths.extend(list("ScaledDirectories"));
+        let dirs = paths
+            .iter()
+            .filter_map(|path| Directory::parse(&index, path))
+            .collect();
+        let roots = roots
+            .into_iter()
+            .map(|root| {
+                let cache = IconCache::open(&root);
+                (root, cache)
+            })
+            .collect();
+        Some((Self { roots, dirs }, list("Inherits")))
+    }
+
+    /// The icon in the directory closest in size, looking into a root's files only when it
+    /// has no usable cache
+    fn find(&self, name: &str, size: u32) -> Option<PathBuf> {
+        let mut best: Option<(u32, PathBuf)> = None;
+        for (root, cache) in &self.roots {
+            let files: Vec<(&str, &str)> = match cache {
+                Some(cache) => cache.lookup(name),
+                None => self
+                    .dirs
+                    .iter()
+                    .flat_map(|dir| EXTENSIONS.map(|ext| (dir.path.as_str(), ext)))
+                    .filter(|(dir, ext)| root.join(dir).join(format!("{name}.{ext}")).is_file())
+                    .collect(),
+            };
+            for (path, ext) in files {
+                // Directories missing from `index.theme` aren't part of the theme
+                let Some(dir) = self.dirs.iter().find(|dir| dir.path == path) else {
+                    continue;
+                };
+                let distance = dir.distance(size);
+                if best.as_ref().is_none_or(|(best, _)| distance < *best) {
+                    best = Some((distance, root.join(path).join(format!("{name}.{ext}"))));
+                    if distance == 0 {
+                        return best.map(|(_, path)| path);
+                    }
+                }
+            }
+        }
+        best.map(|(_, path)| path)
+    }
+}
+
+impl Directory {
+    fn parse(index: &KeyFile, path: &str) -> Option<Self> {
+        let number = |key: &str| index.get(path, key)?.trim().parse::<u32>().ok();
+        let size = number("Size")?;
+        let scale = number("Scale").unwrap_or(1);
+        let (min, max) = match index.get(path, "Type").unwrap_or("Threshold") {
+            "Fixed" => (size, size),
+            "Scalable" => (
+                number("MinSize").unwrap_or(size),
+                number("MaxSize").unwrap_or(size),
+            ),
+            _ => {
+                let threshold = number("Threshold").unwrap_or(2);
+                (
+                    size.saturating_sub(threshold),
+                    size.saturating_add(threshold),
+                )
+            }
+        };
+        Some(Self {
+            path: path.to_string(),
+            min: min.saturating_mul(scale),
+            max: max.saturating_mul(scale),
+        })
+    }
+
+    /// How far `size` is from the sizes this directory's icons are for
+    fn distance(&self, size: u32) -> u32 {
+        if size < self.min {
+            self.min - size
+        } else {
+            size.saturating_sub(self.max)
+        }
+    }
+}
+
+/// An icon outside of any theme, directly in a base directory or in `pixmaps`
+fn find_unthemed(name: &str) -> Option<PathBuf> {
+    let mut dirs = icon_theme::base_dirs();
+    dirs.extend(
+        basedir::data_dirs()
+            .into_iter()
+            .map(|dir| dir.join("pixmaps")),
+    );
+    dirs.iter()
+        .flat_map(|dir| EXTENSIONS.map(|ext| dir.join(format!("{name}.{ext}"))))
+        .find(|path| path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(index: &str, path: &str) -> Option<(u32, u32)> {
+        let dir = Directory::parse(&KeyFile::parse(index), path)?;
+        Some((dir.min, dir.max))
+    }
+
+    #[test]
+    fn directory_sizes() {
+        let index = "\
+[48x48/apps]
+Size=48
+[48x48@2/apps]
+Size=48
+Scale=2
+Threshold=4
+[32x32/fixed]
+Size=32
+Type=Fixed
+[scalable/apps]
+Size=128
+Type=Scalable
+MinSize=8
+MaxSize=512
+[broken]
+Type=Fixed
+";
+        assert_eq!(range(index, "48x48/apps"), Some((46, 50)));
+        assert_eq!(range(index, "48x48@2/apps"), Some((88, 104)));
+        assert_eq!(range(index, "32x32/fixed"), Some((32, 32)));
+        assert_eq!(range(index, "scalable/apps"), Some((8, 512)));
+        assert_eq!(range(index, "broken"), None);
+    }
+
+    #[test]
+    fn huge_sizes_saturate() {
+        let index = "[huge]\nSize=4294967295\nThreshold=10\nScale=3\n";
+        assert_eq!(range(index, "huge"), Some((u32::MAX, u32::MAX)));
+    }
+}
@@ -0,0 +1,309 @@
+//! Minimal parser for the INI-like "key file" format used by freedesktop specs
+//! (`mimeapps.list`, `kdeglobals`, GTK `settings.ini`, ...), and by our own config file.
+//!
+//! The original lines are kept, so saving a file only changes the entries that were set or
+//! removed. Comments, blank lines, formatting and lines we don't understand stay as they were.
+
+use std::{
+    fs::{OpenOptions, Permissions},
+    io::{self, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::Path,
+};
+
+#[derive(Default, Debug)]
+pub struct KeyFile {
+    lines: Vec<Line>,
+}
+
+#[derive(Debug)]
+struct Line {
+    /// The line as it was read, including its line terminator, if any
+    raw: String,
+    kind: Kind,
+}
+
+#[derive(Debug)]
+enum Kind {
+    Group(String),
+    Entry {
+        key: String,
+        value: String,
+    },
+    /// Comments, blank lines, and anything else we leave alone
+    Other,
+}
+
+impl Line {
+    fn new(raw: String) -> Self {
+        let line = raw.trim();
+        let kind = if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            Kind::Other
+        } else if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            Kind::Group(name.to_string())
+        } else if let Some((k, v)) = line.split_once('=') {
+            Kind::Entry {
+                key: k.trim().to_string(),
+                value: v.trim().to_string(),
+            }
+        } else {
+            Kind::Other
+        };
+        Self { raw, kind }
+    }
+
+    fn entry(key: &str, value: &str) -> Self {
+        Self::new(format!("{key}={value}\n"))
+    }
+}
+
+impl KeyFile {
+    pub fn parse(text: &str) -> Self {
+        Self {
+            lines: text
+                .split_inclusive('\n')
+                .map(|raw| Line::new(raw.to_string()))
+                .collect(),
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        std::fs::read_to_string(path).map(|text| Self::parse(&text))
+    }
+
+    /// The lines with the group each is in. Entries before the first group are in none.
+    fn grouped(&self) -> impl Iterator<Item = (Option<&str>, usize, &Line)> {
+        let mut current = None;
+        self.lines.iter().enumerate().map(move |(i, line)| {
+            if let Kind::Group(name) = &line.kind {
+                current = Some(name.as_str());
+            }
+            (current, i, line)
+        })
+    }
+
+    /// Indices of the lines holding `key` in `group`
+    fn entry_lines(&self, group: &str, key: &str) -> Vec<usize> {
+        self.grouped()
+            .filter(|(g, _, line)| {
+                *g == Some(group) && matches!(&line.kind, Kind::Entry { key: k, .. } if k == key)
+            })
+            .map(|(_, i, _)| i)
+            .collect()
+    }
+
+    /// Look up `key` in `group`. If the key appears multiple times, the last one wins.
+    pub fn get(&self, group: &str, key: &str) -> Option<&str> {
+        let last = *self.entry_lines(group, key).last()?;
+        match &self.lines[last].kind {
+            Kind::Entry { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Set `key` in `group` to `value`, replacing all previous values.
+    ///
+    /// The first line of the key is rewritten in place. A new key goes after the last entry
+    /// of the group, and a new group is added at the end.
+    pub fn set(&mut self, group: &str, key: &str, value: &str) {
+        let existing = self.entry_lines(group, key);
+        if let Some((&first, rest)) = existing.split_first() {
+            self.lines[first] = Line::entry(key, value);
+            for &i in rest.iter().rev() {
+                self.lines.remove(i);
+            }
+            return;
+        }
+        // After the last entry or header of the group, so comments and blank lines before the
+        // next group stay with that group
+        let last_in_group = self
+            .grouped()
+            .filter(|(g, _, line)| *g == Some(group) && !matches!(line.kind, Kind::Other))
+            .map(|(_, i, _)| i)
+            .last();
+        match last_in_group {
+            Some(i) => {
+                self.terminate(i);
+                self.lines.insert(i + 1, Line::entry(key, value));
+            }
+            None => {
+                if let Some(last) = self.lines.len().checked_sub(1) {
+                    self.terminate(last);
+                    if !self.lines[last].raw.trim().is_empty() {
+                        self.lines.push(Line::new("\n".to_string()));
+                    }
+                }
+                self.lines.push(Line::new(format!("[{group}]\n")));
+                self.lines.push(Line::entry(key, value));
+            }
+        }
+    }
+
+    /// Rename every `from` key in `group` to `to`, keeping the values and positions
+    pub fn rename(&mut self, group: &str, from: &str, to: &str) {
+        for i in self.entry_lines(group, from) {
+            if let Kind::Entry { value, .. } = &self.lines[i].kind {
+                self.lines[i] = Line::entry(to, &value.clone());
+            }
+        }
+    }
+
+    /// End line `i` with a newline, in case it's the last line of a file without one
+    fn terminate(&mut self, i: usize) {
+        if !self.lines[i].raw.ends_with('\n') {
+            self.lines[i].raw.push('\n');
+        }
+    }
+
+    /// The file's text, with the changes made since it was parsed
+    pub fn text(&self) -> String {
+        self.lines.iter().map(|line| line.raw.as_str()).collect()
+    }
+
+    /// Write the file to `path`.
+    ///
+    /// The text goes to a temporary file next to it first, which then replaces it, so the file
+    /// is never left half written. Its permissions are kept, and if it's a symlink, the file
+    /// it points to is replaced.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Not a file path"))?;
+        let temp = path.with_file_name(format!(
+            ".{}.{}.tmp",
+            name.to_string_lossy(),
+            std::process::id()
+        ));
+        let mode = path
+            .metadata()
+            .ok()
+            .map(|meta| meta.permissions().mode() & 0o7777);
+        // Left behind if an earlier run with the same PID crashed
+        let _ = std::fs::remove_file(&temp);
+        let write = || -> io::Result<()> {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(mode.unwrap_or(0o666))
+                .open(&temp)?;
+            if let Some(mode) = mode {
+                // The umask may have taken bits away
+                file.set_permissions(Permissions::from_mode(mode))?;
+            }
+            file.write_all(self.text().as_bytes())?;
+            file.sync_all()?;
+            std::fs::rename(&temp, &path)
+        };
+        write().inspect_err(|_| {
+            let _ = std::fs::remove_file(&temp);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MIMEAPPS: &str = "\
+# Edited by hand
+[Default Applications]
+text/plain = org.kde.kate.desktop
+image/png=org.gimp.GIMP.desktop;
+
+; what else opens things
+[Added Associations]
+text/plain=gvim.desktop;
+";
+
+    #[test]
+    fn round_trip_keeps_the_text() {
+        for text in [
+            MIMEAPPS,
+            "",
+            "no=group\n[G]\nk=v",
+            "[G]\r\nk = v\r\nnot an entry\r\n",
+        ] {
+            assert_eq!(KeyFile::parse(text).text(), text);
+        }
+    }
+
+    #[test]
+    fn get_trims_and_last_wins() {
+        let kf = KeyFile::parse("top=level\n[G]\n k = first \n[H]\nk=other\n[G]\nk=last\n");
+        assert_eq!(kf.get("G", "k"), Some("last"));
+        assert_eq!(kf.get("H", "k"), Some("other"));
+        assert_eq!(kf.get("G", "top"), None);
+        assert_eq!(kf.get("G", "missing"), None);
+    }
+
+    #[test]
+    fn set_existing_rewrites_only_that_line() {
+        let mut kf = KeyFile::parse(MIMEAPPS);
+        kf.set("Default Applications", "text/plain", "gvim.desktop");
+        assert_eq!(
+            kf.text(),
+            MIMEAPPS.replace(
+                "text/plain = org.kde.kate.desktop",
+                "text/plain=gvim.desktop"
+            )
+        );
+    }
+
+    #[test]
+    fn set_new_key_goes_after_the_group_entries() {
+        let mut kf = KeyFile::parse(MIMEAPPS);
+        kf.set("Default Applications", "text/html", "firefox.desktop");
+        assert_eq!(
+            kf.text(),
+            MIMEAPPS.replace(
+                "image/png=org.gimp.GIMP.desktop;\n",
+                "image/png=org.gimp.GIMP.desktop;\ntext/html=firefox.desktop\n"
+            )
+        );
+    }
+
+    #[test]
+    fn set_new_group_is_appended() {
+        let mut kf = KeyFile::parse("# only a comment");
+        kf.set("General", "k", "v");
+        assert_eq!(kf.text(), "# only a comment\n\n[General]\nk=v\n");
+        let mut kf = KeyFile::default();
+        kf.set("General", "k", "v");
+        assert_eq!(kf.text(), "[General]\nk=v\n");
+    }
+
+    #[test]
+    fn set_collapses_duplicates() {
+        let mut kf = KeyFile::parse("[G]\nk=1\n# keep\nk=2\n");
+        kf.set("G", "k", "3");
+        assert_eq!(kf.text(), "[G]\nk=3\n# keep\n");
+        assert_eq!(kf.get("G", "k"), Some("3"));
+    }
+
+    #[test]
+    fn rename_keeps_values_and_positions() {
+        let mut kf = KeyFile::parse("[G]\na[$e]=1\nb=2\n[H]\na[$e]=3\n");
+        kf.rename("G", "a[$e]", "a");
+        assert_eq!(kf.text(), "[G]\na=1\nb=2\n[H]\na[$e]=3\n");
+    }
+
+    #[test]
+    fn save_replaces_the_file() {
+        let dir = std::env::temp_dir().join(format!("keyfile-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("mimeapps.list");
+        std::fs::write(&path, MIMEAPPS).unwrap();
+        std::fs::set_permissions(&path, Permissions::from_mode(0o640)).unwrap();
+        let mut kf = KeyFile::load(&path).unwrap();
+        kf.set("Default Applications", "image/png", "gimp.desktop");
+        kf.save(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), kf.text());
+        let mode = path.metadata().unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, 0o640);
+        // No temporary file left over
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
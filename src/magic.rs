@@ -0,0 +1,285 @@
+//! Content sniffing with the `magic` files of the shared-mime-info database
+//! (see the "The magic files" section of the shared-mime-info spec)
+
+use std::path::Path;
+
+/// Don't read more than this much of a file, whatever the rules ask for
+pub const MAX_EXTENT: usize = 64 * 1024;
+
+/// A `[priority:type]` section of a magic file
+struct Rule {
+    priority: u32,
+    mime: String,
+    matchlets: Vec<Matchlet>,
+}
+
+/// A single `[indent]>offset=value[&mask][~word-size][+range-length]` line
+struct Matchlet {
+    indent: usize,
+    offset: usize,
+    value: Vec<u8>,
+    mask: Option<Vec<u8>>,
+    range: usize,
+}
+
+#[derive(Default)]
+pub struct Magic {
+    /// Ordered by priority, highest first
+    rules: Vec<Rule>,
+}
+
+impl Magic {
+    /// Load the `magic` files of all `mime_dirs`
+    pub fn load(mime_dirs: &[impl AsRef<Path>]) -> Self {
+        let mut magic = Self::default();
+        for dir in mime_dirs {
+            if let Ok(data) = std::fs::read(dir.as_ref().join("magic")) {
+                magic.rules.extend(parse(&data));
+            }
+        }
+        // Stable, so more important directories still win among equal priorities
+        magic
+            .rules
+            .sort_by_key(|rule| std::cmp::Reverse(rule.priority));
+        magic
+    }
+
+    /// How many bytes from the start of a file the rules look at
+    pub fn extent(&self) -> usize {
+        self.rules
+            .iter()
+            .flat_map(|rule| &rule.matchlets)
+            // The last place the value can start is `range - 1` bytes after the offset
+            .map(|m| m.offset + m.range - 1 + m.value.len())
+            .max()
+            .unwrap_or(0)
+            .min(MAX_EXTENT)
+    }
+
+    /// Types whose rules match `data`, highest priority first
+    pub fn matches<'a>(&'a self, data: &'a [u8]) -> impl Iterator<Item = &'a str> {
+        self.rules
+            .iter()
+            .filter(|rule| any_matches(&rule.matchlets, 0, data))
+            .map(|rule| rule.mime.as_str())
+    }
+}
+
+/// Whether any matchlet at `indent` in `matchlets` matches along with one of its children.
+///
+/// `matchlets` starts at the first candidate and is cut off at the end of the parent's children.
+fn any_matches(matchlets: &[Matchlet], indent: usize, data: &[u8]) -> bool {
+    let mut i = 0;
+    while i < matchlets.len() {
+        let matchlet = &matchlets[i];
+        // Children are all following lines with a deeper indent
+        let children_end = matchlets[i + 1..]
+            .iter()
+            .position(|m| m.indent <= matchlet.indent)
+            .map_or(matchlets.len(), |pos| i + 1 + pos);
+        let children = &matchlets[i + 1..children_end];
+        if matchlet.indent == indent
+            && matchlet.matches(data)
+            && (children.is_empty() || any_matches(children, indent + 1, data))
+        {
+            return true;
+        }
+        i = children_end;
+    }
+    false
+}
+
+impl Matchlet {
+    fn matches(&self, data: &[u8]) -> bool {
+        (self.offset..self.offset + self.range).any(|start| {
+            let Some(window) = data.get(start..start + self.value.len()) else {
+                return false;
+            };
+            match &self.mask {
+                Some(mask) => window
+                    .iter()
+                    .zip(&self.value)
+                    .zip(mask)
+                    .all(|((byte, value), mask)| byte & mask == value & mask),
+                None => window == self.value.as_slice(),
+            }
+        })
+    }
+}
+
+fn parse(data: &[u8]) -> Vec<Rule> {
+    let Some(mut rest) = data.strip_prefix(b"MIME-Magic\0\n") else {
+        return Vec::new();
+    };
+    let mut rules: Vec<Rule> = Vec::new();
+    while !rest.is_empty() {
+        if rest[0] == b'[' {
+            let Some(end) = rest.iter().position(|&b| b == b'\n') else {
+                break;
+            };
+            let header = String::from_utf8_lossy(&rest[1..end]);
+            let (priority, mime) = header
+                .trim_end_matches(']')
+                .split_once(':')
+                .unwrap_or_default();
+            rules.push(Rule {
+                priority: priority.parse().unwrap_or(50),
+                mime: mime.to_string(),
+                matchlets: Vec::new(),
+            });
+            rest = &rest[end + 1..];
+            continue;
+        }
+        match parse_matchlet(rest) {
+            Some((matchlet, after)) => {
+                if let Some(rule) = rules.last_mut() {
+                    rule.matchlets.push(matchlet);
+                }
+                rest = after;
+            }
+            // Lines we don't understand are skipped, as the spec asks
+            None => match rest.iter().position(|&b| b == b'\n') {
+                Some(end) => rest = &rest[end + 1..],
+                None => break,
+            },
+        }
+    }
+    rules
+}
+
+/// Parse one matchlet line, returning it and what follows the line
+fn parse_matchlet(line: &[u8]) -> Option<(Matchlet, &[u8])> {
+    let (indent, rest) = number(line);
+    let rest = rest.strip_prefix(b">")?;
+    let (offset, rest) = number(rest);
+    let rest = rest.strip_prefix(b"=")?;
+    let len = usize::from(u16::from_be_bytes([*rest.first()?, *rest.get(1)?]));
+    let mut value = rest.get(2..2 + len)?.to_vec();
+    let mut rest = &rest[2 + len..];
+    let mut mask = None;
+    if let Some(after) = rest.strip_prefix(b"&") {
+        mask = Some(after.get(..len)?.to_vec());
+        rest = &after[len..];
+    }
+    let mut word_size = 1;
+    if let Some(after) = rest.strip_prefix(b"~") {
+        let (size, after) = number(after);
+        word_size = size.unwrap_or(1);
+        rest = after;
+    }
+    let mut range = 1;
+    if let Some(after) = rest.strip_prefix(b"+") {
+        let (length, after) = number(after);
+        range = length.unwrap_or(1);
+        rest = after;
+    }
+    let rest = rest.strip_prefix(b"\n")?;
+    // Values are stored big endian, but words are compared in host byte order
+    if cfg!(target_endian = "little") && word_size > 1 {
+        for bytes in std::iter::once(&mut value).chain(mask.as_mut()) {
+            for word in bytes.chunks_exact_mut(word_size) {
+                word.reverse();
+            }
+        }
+    }
+    let matchlet = Matchlet {
+        indent: indent.unwrap_or(0),
+        offset: offset?,
+        value,
+        mask,
+        range: range.max(1),
+    };
+    Some((matchlet, rest))
+}
+
+/// A leading decimal number, if there is one, and the rest of the input
+fn number(input: &[u8]) -> (Option<usize>, &[u8]) {
+    let digits = input.iter().take_while(|b| b.is_ascii_digit()).count();
+    let number = std::str::from_utf8(&input[..digits])
+        .ok()
+        .and_then(|digits| digits.parse().ok());
+    (number, &input[digits..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAGIC: &[u8] = b"MIME-Magic\0\n\
+[50:application/x-nested]\n\
+>0=\0\x03abc\n\
+1>10=\0\x01x+5\n\
+this line is junk\n\
+[80:application/x-masked]\n\
+>0=\0\x02\xf0\x0f&\xf0\xf0\n\
+[60:application/x-word]\n\
+>4=\0\x02\x12\x34~2\n";
+
+    fn magic() -> Magic {
+        let mut rules = parse(MAGIC);
+        rules.sort_by_key(|rule| std::cmp::Reverse(rule.priority));
+        Magic { rules }
+    }
+
+    fn matches(data: &[u8]) -> Vec<String> {
+        magic().matches(data).map(str::to_string).collect()
+    }
+
+    #[test]
+    fn parses_headers_and_matchlets() {
+        let rules = parse(MAGIC);
+        let summary: Vec<_> = rules
+            .iter()
+            .map(|rule| (rule.priority, rule.mime.as_str(), rule.matchlets.len()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (50, "application/x-nested", 2),
+                (80, "application/x-masked", 1),
+                (60, "application/x-word", 1),
+            ]
+        );
+        let child = &rules[0].matchlets[1];
+        assert_eq!((child.indent, child.offset, child.range), (1, 10, 5));
+        assert_eq!(
+            rules[1].matchlets[0].mask.as_deref(),
+            Some(&b"\xf0\xf0"[..])
+        );
+        assert!(parse(b"not a magic file").is_empty());
+    }
+
+    #[test]
+    fn extent_covers_ranges() {
+        // `x` at offset 10, anywhere in the next 5 bytes
+        assert_eq!(magic().extent(), 15);
+    }
+
+    #[test]
+    fn nested_matchlets_need_a_child_match() {
+        assert_eq!(matches(b"abc_______x"), ["application/x-nested"]);
+        assert_eq!(matches(b"abc___________x"), ["application/x-nested"]);
+        assert!(matches(b"abc____________x").is_empty());
+        assert!(matches(b"abc").is_empty());
+    }
+
+    #[test]
+    fn masks_and_word_sizes() {
+        assert_eq!(matches(b"\xfa\x05"), ["application/x-masked"]);
+        assert!(matches(b"\x0a\x05").is_empty());
+        // The value is a big endian 16-bit word, compared in host byte order
+        let mut data = b"____".to_vec();
+        data.extend(0x1234u16.to_ne_bytes());
+        assert_eq!(matches(&data), ["application/x-word"]);
+    }
+
+    #[test]
+    fn higher_priorities_come_first() {
+        let mut data = b"\xf0\x00__".to_vec();
+        data.extend(0x1234u16.to_ne_bytes());
+        assert_eq!(
+            matches(&data),
+            ["application/x-masked", "application/x-word"]
+        );
+    }
+}
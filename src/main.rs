@@ -1,6 +1,10 @@
 #![allow(clippy::collapsible_if)]
 
 use {
+    chooser::{Choice, Chooser},
+    command_line::CommandLine,
+    completion::CommandField,
+    countdown::Countdown,
     detect_desktop_environment::DesktopEnvironment,
     egui_sf2g::{
         SfEgui,
@@ -11,22 +15,60 @@ use {
             window::{Event, Style, VideoMode},
         },
     },
-    icon::Icons,
+    file_manager::Method,
+    preview::Preview,
     std::{
         borrow::Cow,
         ffi::{OsStr, OsString},
+        io::Read,
+        os::unix::ffi::OsStrExt,
         path::{Path, PathBuf},
-        process::Command,
+        process::{Command, ExitStatus},
         str::Utf8Error,
+        time::Duration,
     },
+    target::Target,
     thiserror::Error,
+    trace::{StepKind, Trace},
     url::Url,
-    xdg_desktop_file::{args_from_exec_string, parse_desktop_file},
+    xdg_desktop_file::{
+        args_from_command, args_from_exec_string, find_desktop_file, parse_desktop_file,
+    },
 };
 
+mod basedir;
+mod cache_file;
+mod chooser;
+mod cli;
+mod command_line;
+mod completion;
+mod config;
+mod countdown;
+mod data_uri;
+mod email;
+mod explain;
+mod file_manager;
 mod generic_xdg;
+mod handlers;
+mod helper;
+mod icon_cache;
+mod icon_theme;
+mod icons;
+mod json;
+mod keyfile;
+mod magic;
+mod mime_cache;
+mod mimeapps;
+mod preview;
 mod qt_xdg;
+mod settings;
+mod shared_mime;
+mod target;
+mod temp_file;
+mod thumbnail;
+mod trace;
 mod xdg_desktop_file;
+mod xdg_mime;
 
 #[derive(Error, Debug)]
 enum XdgQueryError {
@@ -34,156 +76,561 @@ enum XdgQueryError {
     InvalidUtf8(Utf8Error),
     #[error("Empty response")]
     Empty,
+    #[error("Failed to run helper: {err}")]
+    Spawn { cmd: String, err: std::io::Error },
+    #[error("Helper exited with {status}: {stderr}")]
+    Failed {
+        cmd: String,
+        status: ExitStatus,
+        stdout: String,
+        stderr: String,
+    },
+    #[error("Helper didn't respond within {timeout:?}")]
+    Timeout { cmd: String, timeout: Duration },
+}
+
+impl XdgQueryError {
+    /// The helper command line that failed, if the error came from running a helper
+    fn command(&self) -> Option<&str> {
+        match self {
+            Self::Spawn { cmd, .. } | Self::Failed { cmd, .. } | Self::Timeout { cmd, .. } => {
+                Some(cmd)
+            }
+            Self::InvalidUtf8(_) | Self::Empty => None,
+        }
+    }
 }
 
 trait QueryExt {
-    fn query_mime(&self, arg: &OsStr) -> Result<String, XdgQueryError>;
-    fn query_default(&self, mime: &str) -> Result<String, XdgQueryError>;
+    fn query_mime(&self, arg: &OsStr, trace: &mut Trace) -> Result<String, XdgQueryError>;
+    fn query_default(&self, mime: &str, trace: &mut Trace) -> Result<String, XdgQueryError>;
+    /// Name of the helper program answering the queries
+    fn query_helper(&self) -> &'static str;
 }
 
 impl QueryExt for Option<DesktopEnvironment> {
-    fn query_mime(&self, arg: &OsStr) -> Result<String, XdgQueryError> {
+    fn query_mime(&self, arg: &OsStr, trace: &mut Trace) -> Result<String, XdgQueryError> {
         match self {
-            Some(DesktopEnvironment::Lxqt) => qt_xdg::query_mime(arg),
-            _ => generic_xdg::query_mime_xdg(arg),
+            Some(DesktopEnvironment::Lxqt) => qt_xdg::query_mime(arg, trace),
+            _ => generic_xdg::query_mime_xdg(arg, trace),
         }
     }
 
-    fn query_default(&self, mime: &str) -> Result<String, XdgQueryError> {
+    fn query_default(&self, mime: &str, trace: &mut Trace) -> Result<String, XdgQueryError> {
+        let default = match self {
+            Some(DesktopEnvironment::Lxqt) => qt_xdg::query_default(mime, trace),
+            _ => generic_xdg::query_default(mime, trace),
+        };
+        match default {
+            // How both helpers say there's no default
+            Err(XdgQueryError::Failed { stdout, .. }) if stdout.is_empty() => {
+                Err(XdgQueryError::Empty)
+            }
+            default => default,
+        }
+    }
+
+    fn query_helper(&self) -> &'static str {
         match self {
-            Some(DesktopEnvironment::Lxqt) => qt_xdg::query_default(mime),
-            _ => generic_xdg::query_default(mime),
+            Some(DesktopEnvironment::Lxqt) => "qtxdg-mat",
+            _ => "xdg-mime",
         }
     }
 }
 
-fn open(arg: &OsStr, de: Option<DesktopEnvironment>) -> Status {
-    let mut url_mime = None;
-    let mut url_path = String::new();
-    if let Some(text) = arg.to_str()
-        && let Ok(url) = Url::parse(text)
-    {
-        let scheme = url.scheme();
-        url_path = url.path().to_string();
-        url_mime = Some(format!("x-scheme-handler/{scheme}"));
+/// Determine the MIME type of the local file `path`, unless `mime_override` is given.
+///
+/// The shared-mime-info database is read directly, falling back to the helper of the desktop.
+fn detect_mime(
+    path: &Path,
+    de: Option<DesktopEnvironment>,
+    mime_override: Option<&str>,
+    trace: &mut Trace,
+) -> Result<String, XdgQueryError> {
+    if let Some(mime) = mime_override {
+        trace.push(StepKind::Mime, mime, Some("override".into()));
+        return Ok(mime.to_owned());
     }
-    let mut mime = if let Some(url_mime) = url_mime {
-        url_mime
-    } else {
-        match de.query_mime(arg) {
-            Ok(mime) => mime,
-            Err(err) => {
-                return Status::XdgQueryError {
-                    arg: arg.to_owned(),
-                    extracted_path: None,
+    // The helper is only needed on systems without a MIME database
+    if let Some((mime, how)) = shared_mime::detect(path) {
+        trace.push(
+            StepKind::Mime,
+            &mime,
+            Some(format!("shared-mime-info {how}")),
+        );
+        return Ok(mime);
+    }
+    let mime = de.query_mime(path.as_os_str(), trace)?;
+    trace.push(StepKind::Mime, &mime, Some(de.query_helper().into()));
+    Ok(mime)
+}
+
+/// One-off replacements for what `open` would otherwise determine itself
+#[derive(Default, Clone, Copy)]
+struct Overrides<'a> {
+    /// MIME type to use instead of detecting one
+    mime: Option<&'a str>,
+    /// Desktop ID, path of a desktop file, or command line to use instead of the default
+    /// application
+    with: Option<&'a str>,
+}
+
+/// What we know about an argument once its type is determined
+struct Resolved {
+    /// What to pass to the application
+    launch_arg: OsString,
+    /// Local path of a `file://` URL, or file holding the content of a `data:` URL
+    extracted_path: Option<PathBuf>,
+    mime: String,
+}
+
+/// Classify `arg` and determine its MIME type, unless `mime_override` is given.
+///
+/// With `dry_run`, nothing is written, so the content of `data:` URLs is only decoded.
+fn resolve(
+    arg: &OsStr,
+    de: Option<DesktopEnvironment>,
+    mime_override: Option<&str>,
+    dry_run: bool,
+    trace: &mut Trace,
+) -> Result<Resolved, Box<Status>> {
+    let target = match target::classify(arg, config::get().bare_hostnames()) {
+        Ok(target) => target,
+        Err(err) => {
+            trace.push(StepKind::Classify, err.to_string(), None);
+            return Err(Box::new(Status::InvalidTarget {
+                arg: arg.to_owned(),
+                err,
+            }));
+        }
+    };
+    trace.push(StepKind::Classify, target.describe(), None);
+    let mut launch_arg = target.launch_arg(arg);
+    let mut extracted_path = None;
+    let mime = match &target {
+        Target::Path(path) => {
+            if let Err(err) = path.metadata() {
+                return Err(Box::new(Status::PathError {
+                    path: path.clone(),
                     err,
-                };
+                }));
+            }
+            detect_mime(path, de, mime_override, trace)
+        }
+        // Special handling for `file://` URLs
+        Target::FileUrl(path) => {
+            extracted_path = Some(path.clone());
+            detect_mime(path, de, mime_override, trace)
+        }
+        // `data:` URLs carry their content, which handlers expect as a file
+        Target::Url(url) if url.scheme() == "data" => {
+            match data_uri::materialize(url.as_str(), dry_run) {
+                Ok((path, mime)) => {
+                    trace.push(
+                        StepKind::Mime,
+                        &mime,
+                        Some("media type declared by the data: URL".into()),
+                    );
+                    launch_arg = path.clone().into();
+                    extracted_path = Some(path);
+                    Ok(mime)
+                }
+                Err(err) => return Err(Box::new(Status::DataUriError(err))),
             }
         }
+        Target::Url(url) | Target::Host(url) => {
+            let mime = format!("x-scheme-handler/{}", url.scheme());
+            trace.push(StepKind::Mime, &mime, Some("URL scheme".into()));
+            Ok(mime)
+        }
     };
-    // Special handling for `file://` URLs
-    if mime == "x-scheme-handler/file" {
-        if url_path.contains('%') {
-            url_path = percent_encoding::percent_decode_str(&url_path)
-                .decode_utf8_lossy()
-                .into_owned()
-        }
-        match de.query_mime(url_path.as_ref()) {
-            Ok(path_mime) => {
-                mime = path_mime;
+    let mime = match mime {
+        // The type of local files is already overridden in `detect_mime`
+        Ok(mime) => match mime_override {
+            Some(mime_override) if mime != mime_override => {
+                trace.push(StepKind::Mime, mime_override, Some("override".into()));
+                mime_override.to_owned()
             }
-            Err(err) => {
-                return Status::XdgQueryError {
-                    arg: arg.to_owned(),
-                    extracted_path: Some(url_path),
-                    err,
+            _ => mime,
+        },
+        Err(err) => {
+            return Err(Box::new(Status::XdgQueryError {
+                arg: arg.to_owned(),
+                extracted_path,
+                err,
+            }));
+        }
+    };
+    Ok(Resolved {
+        launch_arg,
+        extracted_path,
+        mime,
+    })
+}
+
+/// Open `target`, which is standard input if it's `-`
+fn open_target(
+    target: &OsStr,
+    de: Option<DesktopEnvironment>,
+    overrides: Overrides,
+    dry_run: bool,
+    trace: &mut Trace,
+) -> Status {
+    if target == "-" {
+        open_stdin(de, overrides, dry_run, trace)
+    } else {
+        open(target, de, overrides, dry_run, trace)
+    }
+}
+
+/// Resolve the application to open `arg` with, normally the default one for its type
+fn open(
+    arg: &OsStr,
+    de: Option<DesktopEnvironment>,
+    overrides: Overrides,
+    dry_run: bool,
+    trace: &mut Trace,
+) -> Status {
+    match resolve(arg, de, overrides.mime, dry_run, trace) {
+        Ok(resolved) => open_resolved(arg, resolved, de, overrides, trace),
+        Err(status) => *status,
+    }
+}
+
+/// Resolve the application to open `arg` with, once its type is known
+fn open_resolved(
+    arg: &OsStr,
+    resolved: Resolved,
+    de: Option<DesktopEnvironment>,
+    overrides: Overrides,
+    trace: &mut Trace,
+) -> Status {
+    let Resolved {
+        launch_arg,
+        extracted_path,
+        mime,
+    } = resolved;
+    // A one-off `--with` wins over one configured for the type
+    let with = match overrides.with {
+        Some(with) => Some((with, "--with override".to_string())),
+        None => config::get().type_override(&mime).map(|with| {
+            let file = config::path().unwrap_or_default();
+            (with, format!("[Overrides] in {}", file.display()))
+        }),
+    };
+    // Prefer letting the file manager show directories, if one is listening on D-Bus
+    if mime == "inode/directory" && with.is_none() {
+        let dir = extracted_path.as_deref().unwrap_or(Path::new(arg));
+        if let Ok(dir) = std::fs::canonicalize(dir)
+            && let Some((to_exec, args)) = file_manager::show(Method::ShowFolders, &dir, trace)
+        {
+            trace.push(
+                StepKind::Default,
+                "org.freedesktop.FileManager1",
+                Some("D-Bus session bus".into()),
+            );
+            return Status::PromptExec {
+                arg: arg.into(),
+                launch_arg,
+                extracted_path,
+                icon_path: None,
+                de,
+                mime,
+                appfile_path: PathBuf::default(),
+                to_exec,
+                args,
+                mime_icon_path: mime_icon("inode/directory", None),
+            };
+        }
+    }
+    let default = match with {
+        Some((with, source)) => {
+            let with = with_as_desktop_id(with).unwrap_or_else(|| with.to_owned());
+            trace.push(StepKind::Default, &with, Some(source));
+            with
+        }
+        None => {
+            let lookup = mimeapps::default_app(&mime);
+            for (path, ids) in &lookup.consulted {
+                let detail = if ids.is_empty() {
+                    "no entry".to_string()
+                } else {
+                    format!("{mime}={}", ids.join(";"))
                 };
+                trace.push(
+                    StepKind::Association,
+                    detail,
+                    Some(path.display().to_string()),
+                );
             }
+            let native = match lookup.winner {
+                Some((id, path)) => {
+                    trace.push(
+                        StepKind::Association,
+                        format!("{id} wins, being the first installed entry"),
+                        Some(path.display().to_string()),
+                    );
+                    Some((id, path))
+                }
+                // Without a default, any application associated with the type will do
+                None => mimeapps::fallback_app(&mime)
+                    .map(|handler| (handler.id, handler.source.path().to_owned())),
+            };
+            let default = match native {
+                Some((id, path)) => Ok((id, path.display().to_string())),
+                // The helper may know about associations we don't, e.g. from `defaults.list`
+                None => de
+                    .query_default(&mime, trace)
+                    .map(|def| (def, de.query_helper().to_string())),
+            };
+            match default {
+                Ok((def, source)) => {
+                    trace.push(StepKind::Default, &def, Some(source));
+                    def
+                }
+                Err(XdgQueryError::Empty) => {
+                    return Status::CouldntDetermineDefault {
+                        arg: launch_arg,
+                        mime,
+                    };
+                }
+                // Without the helper, there's just no default
+                Err(XdgQueryError::Spawn { err, .. })
+                    if err.kind() == std::io::ErrorKind::NotFound =>
+                {
+                    return Status::CouldntDetermineDefault {
+                        arg: launch_arg,
+                        mime,
+                    };
+                }
+                Err(err) => {
+                    return Status::XdgQueryError {
+                        arg: arg.to_owned(),
+                        extracted_path: None,
+                        err,
+                    };
+                }
+            }
+        }
+    };
+    let mut icon_path = None;
+    let mut appfile_path = PathBuf::default();
+    let (to_exec, args) = if default.ends_with(".desktop") {
+        appfile_path = if default.contains('/') {
+            PathBuf::from(&default)
+        } else {
+            match find_desktop_file(&default) {
+                Some(path) => path,
+                None => {
+                    return Status::DesktopFileParseError(std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("No desktop file found for {default}"),
+                    ));
+                }
+            }
+        };
+        let desktop_map = match parse_desktop_file(&appfile_path) {
+            Ok(map) => map,
+            Err(e) => {
+                return Status::DesktopFileParseError(e);
+            }
+        };
+        trace.push(
+            StepKind::DesktopFile,
+            appfile_path.display().to_string(),
+            None,
+        );
+        if let Some(icon) = desktop_map.get("Icon") {
+            icon_path = icons::get().find(icon, 64);
         }
+        match desktop_map.get("Exec") {
+            Some(exec) => match args_from_exec_string(exec, &launch_arg) {
+                Some(tup) => tup,
+                None => return Status::InvalidExecString(exec.clone()),
+            },
+            None => (default.clone(), vec![launch_arg.clone()]),
+        }
+    } else {
+        match args_from_command(&default, &launch_arg) {
+            Some(tup) => tup,
+            None => return Status::InvalidExecString(default),
+        }
+    };
+    let mime_icon_path = mime_icon(&mime, icon_path.as_deref());
+    Status::PromptExec {
+        arg: arg.into(),
+        launch_arg,
+        extracted_path,
+        icon_path,
+        de,
+        mime,
+        appfile_path,
+        to_exec,
+        args,
+        mime_icon_path,
     }
-    let icons = Icons::new();
-    // TODO: Detect icon theme rather than hardcoding `breeze`
-    let theme = icons.theme("breeze").unwrap();
-    // TODO: Is this enough to convert mime to icon name?
-    let icon_name = mime.replace('/', "-");
-    let mut mime_icon_path = None;
-    if let Some(ico) = theme.find_icon(&icon_name, 64, 1) {
-        mime_icon_path = Some(ico.path().to_str().unwrap().to_owned());
+}
+
+/// Path of the icon for `mime`.
+///
+/// URL schemes have no icons of their own, so they get the icon of the application handling
+/// them, if any.
+fn mime_icon(mime: &str, app_icon: Option<&Path>) -> Option<PathBuf> {
+    let names = if mime.starts_with("x-scheme-handler/") {
+        if let Some(app_icon) = app_icon {
+            return Some(app_icon.to_owned());
+        }
+        vec!["applications-internet".to_string()]
+    } else {
+        shared_mime::icon_names(mime)
+    };
+    names.iter().find_map(|name| icons::get().find(name, 64))
+}
+
+/// If `with` names an installed application by desktop ID, with or without the `.desktop`
+/// suffix, its desktop ID
+fn with_as_desktop_id(with: &str) -> Option<String> {
+    if with.ends_with(".desktop") {
+        return Some(with.to_owned());
     }
+    let id = format!("{with}.desktop");
+    find_desktop_file(&id).map(|_| id)
+}
 
-    let default = match de.query_default(&mime) {
-        Ok(def) => Some(def),
-        Err(XdgQueryError::Empty) => {
-            return Status::CouldntDetermineDefault {
-                arg: arg.to_owned(),
-                mime,
+/// Show `arg` in its containing folder, with it selected if the file manager supports it
+fn reveal(arg: &OsStr, de: Option<DesktopEnvironment>, dry_run: bool, trace: &mut Trace) -> Status {
+    let path = match std::fs::canonicalize(arg) {
+        Ok(path) => path,
+        Err(err) => {
+            return Status::PathError {
+                path: arg.into(),
+                err,
             };
         }
+    };
+    if let Some((to_exec, args)) = file_manager::show(Method::ShowItems, &path, trace) {
+        trace.push(
+            StepKind::Default,
+            "org.freedesktop.FileManager1",
+            Some("D-Bus session bus".into()),
+        );
+        return Status::PromptExec {
+            arg: arg.into(),
+            launch_arg: path.clone().into(),
+            extracted_path: None,
+            icon_path: None,
+            de,
+            mime: "inode/directory".into(),
+            appfile_path: PathBuf::default(),
+            to_exec,
+            args,
+            mime_icon_path: mime_icon("inode/directory", None),
+        };
+    }
+    // Without FileManager1, the best we can do is open the containing folder
+    open(
+        path.parent().unwrap_or(&path).as_os_str(),
+        de,
+        Overrides::default(),
+        dry_run,
+        trace,
+    )
+}
+
+/// Save standard input to a temporary file, and open that.
+///
+/// Without a MIME type override, the type is sniffed from the content, since there's no file
+/// name to go by.
+fn open_stdin(
+    de: Option<DesktopEnvironment>,
+    overrides: Overrides,
+    dry_run: bool,
+    trace: &mut Trace,
+) -> Status {
+    if dry_run {
+        return resolve_stdin(de, overrides, trace);
+    }
+    let path = match temp_file::create(None).and_then(|(mut file, path)| {
+        std::io::copy(&mut std::io::stdin().lock(), &mut file)?;
+        Ok(path)
+    }) {
+        Ok(path) => path,
+        Err(err) => return Status::StdinError(err),
+    };
+    trace.push(
+        StepKind::Classify,
+        format!("standard input, saved to {}", path.display()),
+        None,
+    );
+    let mime = match detect_mime(&path, de, overrides.mime, trace) {
+        Ok(mime) => mime,
         Err(err) => {
             return Status::XdgQueryError {
-                arg: arg.to_owned(),
-                extracted_path: None,
+                arg: "-".into(),
+                extracted_path: Some(path),
                 err,
             };
         }
     };
-    match default {
-        Some(default) => {
-            let mut icon_path = None;
-            let mut args = &[arg.to_owned()][..];
-            let mut to_exec = &default;
-            let parsed_args;
-            let parsed_exec;
-            let mut appfile_path = PathBuf::default();
-            if default.ends_with(".desktop") {
-                appfile_path = Path::new("/usr/share/applications").join(&default);
-                let desktop_map = match parse_desktop_file(&appfile_path) {
-                    Ok(map) => map,
-                    Err(_) => {
-                        appfile_path = dirs::data_dir()
-                            .unwrap()
-                            .join("applications")
-                            .join(&default);
-                        match parse_desktop_file(&appfile_path) {
-                            Ok(map) => map,
-                            Err(e) => {
-                                return Status::DesktopFileParseError(e);
-                            }
-                        }
-                    }
-                };
-                if let Some(exec) = desktop_map.get("Exec") {
-                    if let Some(tup) = args_from_exec_string(exec, arg) {
-                        (parsed_exec, parsed_args) = tup;
-                        args = &parsed_args[..];
-                        to_exec = &parsed_exec;
-                    } else {
-                        return Status::InvalidExecString(exec.clone());
-                    }
-                }
-                if let Some(icon) = desktop_map.get("Icon") {
-                    if let Some(icon) = Icons::new().find_default_icon(icon, 64, 1) {
-                        icon_path = Some(icon.path().to_str().unwrap().to_owned());
-                    }
-                }
-            }
-            Status::PromptExec {
-                arg: arg.into(),
-                extracted_path: (!url_path.is_empty()).then_some(url_path),
-                icon_path,
-                de,
-                mime,
-                appfile_path,
-                to_exec: to_exec.clone(),
-                args: args.to_vec(),
-                mime_icon_path,
+    // Many applications go by the extension, so give the file a fitting one
+    let path = match shared_mime::preferred_extension(&mime) {
+        Some(ext) => {
+            let renamed = path.with_extension(ext);
+            match std::fs::rename(&path, &renamed) {
+                Ok(()) => renamed,
+                Err(err) => return Status::StdinError(err),
             }
         }
-        None => Status::CouldntDetermineDefault {
-            arg: arg.to_owned(),
-            mime,
-        },
+        None => path,
+    };
+    let overrides = Overrides {
+        mime: Some(&mime),
+        ..overrides
+    };
+    open(path.as_os_str(), de, overrides, false, trace)
+}
+
+/// Read standard input into memory and resolve how it would be opened, without saving it
+fn resolve_stdin(
+    de: Option<DesktopEnvironment>,
+    overrides: Overrides,
+    trace: &mut Trace,
+) -> Status {
+    let mut data = Vec::new();
+    if let Err(err) = std::io::stdin().lock().read_to_end(&mut data) {
+        return Status::StdinError(err);
     }
+    let (mime, how) = match overrides.mime {
+        Some(mime) => (mime.to_owned(), "override".to_string()),
+        None => match shared_mime::detect_data(&data) {
+            Some((mime, how)) => (mime, format!("shared-mime-info {how}")),
+            // Helpers only detect the type of files
+            None => (
+                "application/octet-stream".to_string(),
+                "no MIME database".to_string(),
+            ),
+        },
+    };
+    let path = match temp_file::new_path(shared_mime::preferred_extension(&mime).as_deref()) {
+        Ok(path) => path,
+        Err(err) => return Status::StdinError(err),
+    };
+    trace.push(
+        StepKind::Classify,
+        format!(
+            "standard input, {} bytes, which would be saved to {}",
+            data.len(),
+            path.display()
+        ),
+        None,
+    );
+    trace.push(StepKind::Mime, &mime, Some(how));
+    let resolved = Resolved {
+        launch_arg: path.clone().into(),
+        extracted_path: Some(path.clone()),
+        mime,
+    };
+    open_resolved(path.as_os_str(), resolved, de, overrides, trace)
 }
 
 fn de_opt_str(de: Option<DesktopEnvironment>) -> &'static str {
@@ -221,10 +668,20 @@ enum Status {
     NoArgs,
     XdgQueryError {
         arg: OsString,
-        // Extracted path from `file://` handling
-        extracted_path: Option<String>,
+        // Local path of a `file://` URL, or file holding the content of a `data:` URL
+        extracted_path: Option<PathBuf>,
         err: XdgQueryError,
     },
+    InvalidTarget {
+        arg: OsString,
+        err: target::ClassifyError,
+    },
+    PathError {
+        path: PathBuf,
+        err: std::io::Error,
+    },
+    DataUriError(data_uri::DataUriError),
+    StdinError(std::io::Error),
     DesktopFileParseError(std::io::Error),
     InvalidExecString(String),
     CouldntDetermineDefault {
@@ -233,23 +690,161 @@ enum Status {
     },
     PromptExec {
         arg: OsString,
-        // Extracted path from `file://` handling
-        extracted_path: Option<String>,
+        // What's passed to the application, which an application chosen instead gets as well
+        launch_arg: OsString,
+        // Local path of a `file://` URL, or file holding the content of a `data:` URL
+        extracted_path: Option<PathBuf>,
         de: Option<DesktopEnvironment>,
         mime: String,
         appfile_path: PathBuf,
         to_exec: String,
         args: Vec<OsString>,
         // Path of the `.desktop` icon of the application, if any
-        icon_path: Option<String>,
+        icon_path: Option<PathBuf>,
         // Path of the icon of the detected mime type, if any
-        mime_icon_path: Option<String>,
+        mime_icon_path: Option<PathBuf>,
     },
     ExecError(std::io::Error),
 }
 
+impl Status {
+    /// The `xdg-open` compatible exit code for ending up in this status
+    /// without having launched anything
+    fn exit_code(&self) -> i32 {
+        match self {
+            Self::NoArgs => cli::EXIT_SYNTAX,
+            Self::XdgQueryError {
+                err: XdgQueryError::Spawn { err, .. },
+                ..
+            } if err.kind() == std::io::ErrorKind::NotFound => cli::EXIT_TOOL_MISSING,
+            // Files on other hosts don't exist as far as we're concerned
+            Self::InvalidTarget { .. } => cli::EXIT_NOT_FOUND,
+            Self::PathError { err, .. } if err.kind() == std::io::ErrorKind::NotFound => {
+                cli::EXIT_NOT_FOUND
+            }
+            Self::CouldntDetermineDefault { .. } => cli::EXIT_TOOL_MISSING,
+            Self::ExecError(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                cli::EXIT_TOOL_MISSING
+            }
+            Self::XdgQueryError { .. }
+            | Self::DataUriError(_)
+            | Self::StdinError(_)
+            | Self::DesktopFileParseError(_)
+            | Self::InvalidExecString(_)
+            | Self::PathError { .. }
+            | Self::ExecError(_)
+            // Cancelled by the user
+            | Self::PromptExec { .. } => cli::EXIT_FAILED,
+        }
+    }
+}
+
+impl Status {
+    /// Description of the problem, if this is an error status
+    fn error_message(&self) -> Option<String> {
+        let msg = match self {
+            Self::NoArgs => "No arguments provided".to_string(),
+            Self::XdgQueryError { err, .. } => match err.command() {
+                Some(cmd) => format!("{err} (running `{cmd}`)"),
+                None => err.to_string(),
+            },
+            Self::InvalidTarget { err, .. } => err.to_string(),
+            Self::PathError { path, err } => format!("{}: {err}", display_os(path.as_os_str())),
+            Self::DataUriError(err) => err.to_string(),
+            Self::StdinError(err) => format!("Couldn't save standard input: {err}"),
+            Self::DesktopFileParseError(err) => format!("Desktop file parse error: {err}"),
+            Self::InvalidExecString(exec) => format!("Invalid exec string: {exec}"),
+            Self::CouldntDetermineDefault { mime, .. } => {
+                format!("Couldn't determine default application for {mime}")
+            }
+            Self::ExecError(err) => format!("Exec error: {err}"),
+            Self::PromptExec { .. } => return None,
+        };
+        Some(msg)
+    }
+}
+
 fn main() {
+    let mut args = std::env::args_os();
+    // Symlinked as e.g. `xdg-open`, behave like it
+    let argv0 = args.next().unwrap_or_default();
+    let cli = match cli::parse_as(cli::Flavor::from_argv0(&argv0), args) {
+        Ok(cli) => cli,
+        Err(err) => {
+            let program = Path::new(&argv0)
+                .file_name()
+                .map_or(Cow::Borrowed("rusty-open"), OsStr::to_string_lossy);
+            eprintln!("{program}: {err}");
+            eprintln!("Try '{program} --help' for more information.");
+            std::process::exit(err.exit_code());
+        }
+    };
+    // Nothing to sweep up after for the manual and such, and reports write nothing
+    let uses_temp_files = !matches!(
+        cli.command,
+        cli::Command::Help | cli::Command::Manual | cli::Command::Version
+    );
+    if uses_temp_files && cli.report.is_none() {
+        temp_file::sweep();
+    }
     let de = DesktopEnvironment::detect();
+    let mut trace = Trace::default();
+    // Targets beyond the first, opened by processes of their own
+    let mut others = Vec::new();
+    let options = forwarded_options(&cli);
+    // Reports only tell how things would be opened, so nothing is written for them either
+    let dry_run = cli.report.is_some();
+    let mut status = match cli.command {
+        cli::Command::Help => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        cli::Command::Manual => {
+            print!("{}", cli::MANUAL);
+            return;
+        }
+        cli::Command::Version => {
+            println!("rusty-open {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        cli::Command::NoArgs => Status::NoArgs,
+        cli::Command::Open { target, mime, with } => {
+            let overrides = Overrides {
+                mime: mime.as_deref(),
+                with: with.as_deref(),
+            };
+            open_target(&target, de, overrides, dry_run, &mut trace)
+        }
+        cli::Command::Reveal(path) => reveal(&path, de, dry_run, &mut trace),
+        cli::Command::Mime(command) => std::process::exit(xdg_mime::run(command)),
+        cli::Command::Email(email) => email::compose(email, de, &mut trace),
+        cli::Command::OpenAll(mut targets) => {
+            // Every other location gets its own process, and with that its own prompt
+            others = targets.split_off(1);
+            open_target(&targets[0], de, Overrides::default(), dry_run, &mut trace)
+        }
+        cli::Command::Settings(command) => std::process::exit(settings::run(command)),
+        cli::Command::Handlers(query) => {
+            std::process::exit(handlers::run(&query, de, cli.json, &mut trace));
+        }
+    };
+    if let Some(report) = cli.report {
+        explain::print(&status, &trace, report, cli.json);
+        spawn_others(&others, &options, true);
+        std::process::exit(match status {
+            Status::PromptExec { .. } => 0,
+            _ => status.exit_code(),
+        });
+    }
+    spawn_others(&others, &options, false);
+    if (cli.no_prompt || config::get().no_prompt())
+        && let Status::PromptExec { to_exec, args, .. } = &status
+    {
+        match spawn_command(to_exec, args) {
+            Ok(()) => return,
+            Err(e) => status = Status::ExecError(e),
+        }
+    }
     let default_w = 320;
     let default_h = 80;
     let mut current_w = default_w;
@@ -266,11 +861,39 @@ fn main() {
     let mut sf_egui = SfEgui::new(&rw);
     set_up_style(&sf_egui);
     egui_extras::install_image_loaders(sf_egui.context());
-    let mut status = Status::NoArgs;
-    if let Some(arg) = std::env::args_os().nth(1) {
-        status = open(&arg, de);
-    }
-    let mut fallback_exec_string = String::new();
+    let mut launched = false;
+    let mut fallback_command = CommandField::default();
+    // Shown instead of the status while picking another application
+    let mut chooser: Option<Chooser> = None;
+    let mut command_line = match &status {
+        Status::PromptExec { to_exec, args, .. } => CommandLine::new(to_exec, args),
+        _ => None,
+    };
+    let mut countdown = match &status {
+        Status::PromptExec { mime, .. } => config::get().countdown(mime).map(Countdown::new),
+        _ => None,
+    };
+    // Only local files have something to preview
+    let local_file = match &status {
+        Status::PromptExec {
+            launch_arg,
+            extracted_path,
+            mime,
+            ..
+        } if !mime.starts_with("x-scheme-handler/") => Some((
+            extracted_path
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(launch_arg)),
+            mime.clone(),
+        )),
+        _ => None,
+    };
+    let mut preview = local_file
+        .as_ref()
+        .map(|(path, mime)| Preview::new(path, mime));
+    let thumbnail_path = local_file
+        .as_ref()
+        .and_then(|(path, _)| thumbnail::find(path));
     while rw.is_open() {
         while let Some(ev) = rw.poll_event() {
             sf_egui.add_event(&ev);
@@ -289,181 +912,313 @@ fn main() {
         }
         let di = sf_egui
             .run(&mut rw, |rw, ctx| {
+                if countdown.is_some() && Countdown::interrupted(ctx) {
+                    countdown = None;
+                }
                 egui::CentralPanel::default().show(ctx, |ui| {
-                    match &status {
-                        Status::NoArgs => {
-                            ui.label(format!("Rusty-open running on {}.", de_opt_str(de)));
-                            ui.label("No arguments provided. Nothing to do.");
-                            ui.vertical_centered(|ui| {
-                                if ui.button("Okay then").clicked() {
-                                    rw.close();
+                    if let Some(ch) = &mut chooser {
+                        match ch.show(ui) {
+                            Some(Choice::Launch(to_exec, args)) => {
+                                match spawn_command(&to_exec, &args) {
+                                    Ok(()) => {
+                                        launched = true;
+                                        rw.close();
+                                        return;
+                                    }
+                                    Err(e) => status = Status::ExecError(e),
                                 }
-                            });
+                                chooser = None;
+                            }
+                            Some(Choice::InvalidExec(exec)) => {
+                                status = Status::InvalidExecString(exec);
+                                chooser = None;
+                            }
+                            Some(Choice::Back) => chooser = None,
+                            None => {}
                         }
-                        Status::XdgQueryError {
-                            arg,
-                            err,
-                            extracted_path,
-                        } => {
-                            ui.vertical_centered(|ui| {
-                                ui.heading("XDG Query error");
+                    } else {
+                        match &status {
+                            Status::NoArgs => {
+                                ui.label(format!("Rusty-open running on {}.", de_opt_str(de)));
+                                ui.label("No arguments provided. Nothing to do.");
+                                ui.vertical_centered(|ui| {
+                                    if ui.button("Okay then").clicked() {
+                                        rw.close();
+                                    }
+                                });
+                            }
+                            Status::XdgQueryError {
+                                arg,
+                                err,
+                                extracted_path,
+                            } => {
+                                ui.vertical_centered(|ui| {
+                                    ui.heading("XDG Query error");
 
+                                    egui::Grid::new("info_grid").show(ui, |ui| {
+                                        ui.label("xdg-open arg");
+                                        ui.code(display_os(arg));
+                                        ui.end_row();
+                                        if let Some(decoded) = extracted_path {
+                                            ui.label("Extracted path");
+                                            ui.code(display_os(decoded.as_os_str()));
+                                            ui.end_row();
+                                        }
+                                        if let Some(cmd) = err.command() {
+                                            ui.label("Command");
+                                            ui.code(cmd);
+                                            ui.end_row();
+                                        }
+                                        ui.label("Error");
+                                        ui.code(err.to_string());
+                                    });
+                                    ui.vertical_centered(|ui| {
+                                        if ui.button("Ok").clicked() {
+                                            rw.close();
+                                        }
+                                    });
+                                });
+                            }
+                            Status::InvalidTarget { arg, err } => {
+                                ui.heading("Can't open argument");
                                 egui::Grid::new("info_grid").show(ui, |ui| {
                                     ui.label("xdg-open arg");
-                                    ui.code(arg.display().to_string());
+                                    ui.code(display_os(arg));
                                     ui.end_row();
-                                    if let Some(decoded) = extracted_path {
-                                        ui.label("Extracted path");
-                                        ui.code(decoded);
-                                        ui.end_row();
-                                    }
                                     ui.label("Error");
                                     ui.code(err.to_string());
                                 });
+                            }
+                            Status::PathError { path, err } => {
+                                ui.heading("Couldn't access path");
+                                egui::Grid::new("info_grid").show(ui, |ui| {
+                                    ui.label("Path");
+                                    ui.code(display_os(path.as_os_str()));
+                                    ui.end_row();
+                                    ui.label("Error");
+                                    ui.code(err.to_string());
+                                });
+                            }
+                            Status::DataUriError(err) => {
+                                ui.heading("Couldn't decode data: URL");
+                                ui.code(err.to_string());
+                            }
+                            Status::StdinError(err) => {
+                                ui.heading("Couldn't save standard input");
+                                ui.code(err.to_string());
+                            }
+                            Status::DesktopFileParseError(error) => {
+                                ui.heading("Desktop file parse error");
+                                ui.code(error.to_string());
+                            }
+                            Status::InvalidExecString(s) => {
+                                ui.heading("Invalid exec string");
+                                ui.code(s);
+                            }
+                            Status::CouldntDetermineDefault { arg, mime } => {
+                                ui.heading("Couldn't determine default application");
+                                let mut err = None;
+                                let mut invalid = None;
+                                egui::Grid::new("info_grid").show(ui, |ui| {
+                                    ui.label("Mime");
+                                    ui.code(mime);
+                                    ui.end_row();
+                                    ui.label("Arg string");
+                                    ui.code(display_os(arg));
+                                    ui.end_row();
+                                    ui.label("Command");
+                                    fallback_command.show(ui);
+                                });
                                 ui.vertical_centered(|ui| {
-                                    if ui.button("Ok").clicked() {
+                                    let [k_enter, k_esc, k_choose] = ui.input(|inp| {
+                                        [
+                                            inp.key_pressed(egui::Key::Enter),
+                                            inp.key_pressed(egui::Key::Escape),
+                                            inp.modifiers.ctrl && inp.key_pressed(egui::Key::O),
+                                        ]
+                                    });
+                                    let runnable = fallback_command.is_runnable();
+                                    if ui
+                                        .add_enabled(runnable, egui::Button::new("✔ Run (Enter)"))
+                                        .clicked()
+                                        || (k_enter && runnable)
+                                    {
+                                        // `%f` is where the file goes, it's appended otherwise
+                                        match args_from_command(&fallback_command.text, arg) {
+                                            Some((to_exec, args)) => {
+                                                match spawn_command(&to_exec, &args) {
+                                                    Ok(()) => {
+                                                        launched = true;
+                                                        rw.close();
+                                                        return;
+                                                    }
+                                                    Err(e) => err = Some(e),
+                                                }
+                                            }
+                                            None => {
+                                                invalid = Some(fallback_command.text.clone());
+                                            }
+                                        }
+                                    }
+                                    if ui.button("🗙 Cancel (Escape)").clicked() || k_esc {
                                         rw.close();
                                     }
+                                    if ui.button("☰ Choose application… (Ctrl+O)").clicked()
+                                        || k_choose
+                                    {
+                                        chooser = Some(Chooser::new(arg, mime));
+                                    }
                                 });
-                            });
-                        }
-                        Status::DesktopFileParseError(error) => {
-                            ui.heading("Desktop file parse error");
-                            ui.code(error.to_string());
-                        }
-                        Status::InvalidExecString(s) => {
-                            ui.heading("Invalid exec string");
-                            ui.code(s);
-                        }
-                        Status::CouldntDetermineDefault { arg, mime } => {
-                            ui.heading("Couldn't determine default application");
-                            let mut err = None;
-                            egui::Grid::new("info_grid").show(ui, |ui| {
-                                ui.label("Mime");
-                                ui.code(mime);
-                                ui.end_row();
-                                ui.label("Arg string");
-                                ui.code(arg.display().to_string());
-                                ui.end_row();
-                                ui.label("Path to executable");
-                                ui.text_edit_singleline(&mut fallback_exec_string);
-                            });
-                            ui.vertical_centered(|ui| {
-                                let [k_enter, k_esc] = ui.input(|inp| {
-                                    [
-                                        inp.key_pressed(egui::Key::Enter),
-                                        inp.key_pressed(egui::Key::Escape),
-                                    ]
-                                });
-                                if ui.button("✔ Run (Enter)").clicked() || k_enter {
-                                    match spawn_command(&fallback_exec_string, &[arg.to_owned()]) {
-                                        Ok(()) => {
-                                            rw.close();
-                                            return;
+                                if let Some(e) = err {
+                                    status = Status::ExecError(e);
+                                } else if let Some(cmd) = invalid {
+                                    status = Status::InvalidExecString(cmd);
+                                }
+                            }
+                            Status::ExecError(err) => {
+                                ui.heading("Exec error");
+                                ui.code(err.to_string());
+                            }
+                            Status::PromptExec {
+                                arg,
+                                launch_arg,
+                                extracted_path,
+                                de,
+                                mime,
+                                appfile_path,
+                                to_exec,
+                                args,
+                                icon_path,
+                                mime_icon_path,
+                            } => {
+                                let mut err = None;
+                                let mut invalid = None;
+                                egui::Grid::new("info_grid").show(ui, |ui| {
+                                    ui.label("xdg-open arg");
+                                    ui.code(display_os(arg));
+                                    ui.end_row();
+                                    if let Some(decoded) = extracted_path {
+                                        ui.label("Extracted path");
+                                        ui.code(display_os(decoded.as_os_str()));
+                                        ui.end_row();
+                                    }
+                                    ui.label("Detected DE");
+                                    ui.label(de_opt_str(*de));
+                                    ui.end_row();
+                                    ui.label("Mime type");
+                                    ui.horizontal(|ui| {
+                                        if let Some(icon_path) = mime_icon_path {
+                                            let image = egui::Image::new(egui::ImageSource::Uri(
+                                                Cow::Owned(file_uri(icon_path)),
+                                            ))
+                                            .fit_to_original_size(1.0);
+                                            let _ = ui.selectable_label(false, (image, &*mime));
+                                        } else {
+                                            ui.code(&*mime);
                                         }
-                                        Err(e) => {
-                                            err = Some(e);
+                                        // Made by the file manager, e.g. of PDFs and videos
+                                        if let Some(thumbnail_path) = &thumbnail_path {
+                                            ui.add(
+                                                egui::Image::new(egui::ImageSource::Uri(
+                                                    Cow::Owned(file_uri(thumbnail_path)),
+                                                ))
+                                                .max_size(egui::vec2(128., 128.)),
+                                            );
                                         }
+                                    });
+                                    ui.end_row();
+                                    ui.label(".desktop file");
+                                    ui.code(display_os(appfile_path.as_os_str()));
+                                    ui.end_row();
+                                    ui.label("Executable");
+                                    if let Some(icon_path) = icon_path {
+                                        let image = egui::Image::new(egui::ImageSource::Uri(
+                                            Cow::Owned(file_uri(icon_path)),
+                                        ))
+                                        .fit_to_original_size(1.0);
+                                        let _ = ui.selectable_label(false, (image, to_exec));
+                                    } else {
+                                        ui.code(to_exec);
                                     }
-                                }
-                                if ui.button("🗙 Cancel (Escape)").clicked() || k_esc {
-                                    rw.close();
-                                }
-                            });
-                            if let Some(e) = err {
-                                status = Status::ExecError(e);
-                            }
-                        }
-                        Status::ExecError(err) => {
-                            ui.heading("Exec error");
-                            ui.code(err.to_string());
-                        }
-                        Status::PromptExec {
-                            arg,
-                            extracted_path,
-                            de,
-                            mime,
-                            appfile_path,
-                            to_exec,
-                            args,
-                            icon_path,
-                            mime_icon_path,
-                        } => {
-                            let mut err = None;
-                            egui::Grid::new("info_grid").show(ui, |ui| {
-                                ui.label("xdg-open arg");
-                                ui.code(arg.display().to_string());
-                                ui.end_row();
-                                if let Some(decoded) = extracted_path {
-                                    ui.label("Extracted path");
-                                    ui.code(decoded);
                                     ui.end_row();
-                                }
-                                ui.label("Detected DE");
-                                ui.label(de_opt_str(*de));
-                                ui.end_row();
-                                ui.label("Mime type");
-                                if let Some(icon_path) = mime_icon_path {
-                                    let image = egui::Image::new(egui::ImageSource::Uri(
-                                        Cow::Owned(format!("file://{icon_path}")),
-                                    ))
-                                    .fit_to_original_size(1.0);
-                                    ui.selectable_label(false, (image, mime));
-                                } else {
-                                    ui.code(mime);
-                                }
-                                ui.end_row();
-                                ui.label(".desktop file");
-                                ui.code(appfile_path.display().to_string());
-                                ui.end_row();
-                                ui.label("Executable");
-                                if let Some(icon_path) = icon_path {
-                                    let image = egui::Image::new(egui::ImageSource::Uri(
-                                        Cow::Owned(format!("file://{icon_path}")),
-                                    ))
-                                    .fit_to_original_size(1.0);
-                                    ui.selectable_label(false, (image, to_exec));
-                                } else {
-                                    ui.code(to_exec);
-                                }
-                                ui.end_row();
-                                ui.label("arguments");
-                                ui.end_row();
-                            });
-                            ui.indent("args_indent", |ui| {
-                                for arg in args {
-                                    ui.code(arg.display().to_string());
+                                    if let Some(command_line) = &mut command_line {
+                                        ui.label("Command");
+                                        command_line.show(ui, mime);
+                                    } else {
+                                        ui.label("arguments");
+                                    }
                                     ui.end_row();
-                                }
-                            });
-                            ui.separator();
-                            ui.vertical_centered(|ui| {
-                                let [k_enter, k_esc] = ui.input(|inp| {
-                                    [
-                                        inp.key_pressed(egui::Key::Enter),
-                                        inp.key_pressed(egui::Key::Escape),
-                                    ]
                                 });
-                                if ui.button("✔ Run (Enter)").clicked() || k_enter {
-                                    match spawn_command(to_exec, args) {
-                                        Ok(()) => {
-                                            rw.close();
-                                            return;
+                                // Arguments that aren't valid UTF-8 can only be shown
+                                if command_line.is_none() {
+                                    ui.indent("args_indent", |ui| {
+                                        for arg in args {
+                                            ui.code(display_os(arg));
+                                            ui.end_row();
                                         }
-                                        Err(e) => {
-                                            err = Some(e);
+                                    });
+                                }
+                                if let Some(preview) = &mut preview {
+                                    preview.show(ui);
+                                }
+                                ui.separator();
+                                if let Some(countdown) = &countdown {
+                                    countdown.show(ui);
+                                }
+                                let time_up = countdown.as_ref().is_some_and(Countdown::is_over);
+                                ui.vertical_centered(|ui| {
+                                    let [k_enter, k_esc, k_choose] = ui.input(|inp| {
+                                        [
+                                            inp.key_pressed(egui::Key::Enter),
+                                            inp.key_pressed(egui::Key::Escape),
+                                            inp.modifiers.ctrl && inp.key_pressed(egui::Key::O),
+                                        ]
+                                    });
+                                    if ui.button("✔ Run (Enter)").clicked() || k_enter || time_up
+                                    {
+                                        let edited =
+                                            command_line.as_ref().filter(|cl| cl.is_edited());
+                                        let command = match edited {
+                                            Some(cl) => cl.argv(),
+                                            None => Some((to_exec.clone(), args.clone())),
+                                        };
+                                        match command {
+                                            None => {
+                                                invalid = edited.map(|cl| cl.text.clone());
+                                            }
+                                            Some((to_exec, args)) => {
+                                                match spawn_command(&to_exec, &args) {
+                                                    Ok(()) => {
+                                                        if let Some(cl) = edited {
+                                                            save_override(cl, launch_arg, mime);
+                                                        }
+                                                        launched = true;
+                                                        rw.close();
+                                                        return;
+                                                    }
+                                                    Err(e) => {
+                                                        err = Some(e);
+                                                    }
+                                                }
+                                            }
                                         }
                                     }
+                                    if ui.button("🗙 Cancel (Escape)").clicked() || k_esc {
+                                        rw.close();
+                                    }
+                                    if ui.button("☰ Open with… (Ctrl+O)").clicked() || k_choose
+                                    {
+                                        chooser = Some(Chooser::new(launch_arg, mime));
+                                    }
+                                });
+                                if let Some(e) = err {
+                                    status = Status::ExecError(e);
+                                } else if let Some(cmd) = invalid {
+                                    status = Status::InvalidExecString(cmd);
                                 }
-                                if ui.button("🗙 Cancel (Escape)").clicked() || k_esc {
-                                    rw.close();
-                                }
-                            });
-                            if let Some(e) = err {
-                                status = Status::ExecError(e);
                             }
-                        }
-                    };
+                        };
+                    }
                     let ui_rect = ui.max_rect();
                     let content_w = ui_rect.width() as u32;
                     let content_h = ui_rect.height() as u32;
@@ -490,6 +1245,7 @@ fn main() {
         sf_egui.draw(di, &mut rw, None);
         rw.display();
     }
+    std::process::exit(if launched { 0 } else { status.exit_code() });
 }
 
 fn set_up_style(sf_egui: &SfEgui) {
@@ -513,6 +1269,81 @@ fn spawn_command(cmd: &str, args: &[OsString]) -> std::io::Result<()> {
     Command::new(cmd).args(args).spawn().map(|_| ())
 }
 
+/// Save the edited command `cl` as the override of `mime`, if asked to.
+///
+/// The command has been launched by then, so a failure is only reported.
+fn save_override(cl: &CommandLine, launch_arg: &OsStr, mime: &str) {
+    if cl.save
+        && let Some(with) = cl.as_override(launch_arg)
+        && let Err(err) = config::save_type_override(mime, &with)
+    {
+        eprintln!("rusty-open: Couldn't save override: {err}");
+    }
+}
+
+/// Open each of `targets` in a new rusty-open process, with `options`.
+///
+/// With a report, we wait for each process, so reports are printed one after another.
+fn spawn_others(targets: &[OsString], options: &[OsString], report: bool) {
+    for target in targets {
+        if let Err(err) = spawn_self(target, options, report) {
+            eprintln!("rusty-open: Couldn't open {}: {err}", display_os(target));
+        }
+    }
+}
+
+/// Open `target` in a separate instance of ourselves, waiting for it to finish with `wait`
+fn spawn_self(target: &OsStr, options: &[OsString], wait: bool) -> std::io::Result<()> {
+    let mut child = Command::new(std::env::current_exe()?)
+        .args(options)
+        .arg("--")
+        .arg(target)
+        .spawn()?;
+    if wait {
+        child.wait()?;
+    }
+    Ok(())
+}
+
+/// Options that other rusty-open processes need to treat their targets like we do ours
+fn forwarded_options(cli: &cli::Cli) -> Vec<OsString> {
+    let mut options: Vec<OsString> = Vec::new();
+    if cli.no_prompt {
+        options.push("--no-prompt".into());
+    }
+    match cli.report {
+        Some(cli::Report::DryRun) => options.push("--dry-run".into()),
+        Some(cli::Report::Explain) => options.push("--explain".into()),
+        None => {}
+    }
+    if cli.json {
+        options.push("--json".into());
+    }
+    options
+}
+
+/// Text for showing an OS string in the UI.
+///
+/// Bytes that aren't valid UTF-8 are shown as `\xNN` escapes, so names that differ only in
+/// those bytes can still be told apart.
+fn display_os(s: &OsStr) -> String {
+    let mut out = String::new();
+    for chunk in s.as_bytes().utf8_chunks() {
+        out.push_str(chunk.valid());
+        for byte in chunk.invalid() {
+            out.push_str(&format!("\\x{byte:02X}"));
+        }
+    }
+    out
+}
+
+/// `file://` URI for loading `path` through egui's image loaders
+fn file_uri(path: &Path) -> String {
+    Url::from_file_path(path)
+        .map(String::from)
+        .unwrap_or_else(|()| format!("file://{}", path.display()))
+}
+
 fn center_window(rw: &mut RenderWindow) {
     let Vector2 { x, y } = rw.size();
     let desktop_mode = VideoMode::desktop_mode();
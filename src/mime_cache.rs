@@ -0,0 +1,824 @@
+//! Reader for `mime.cache`, the binary index of the shared-mime-info database that
+//! `update-mime-database` writes along with the text files
+//!
+//! The file is read once and looked up in place, without building any structures from it.
+//! Its lists are sorted, so types, aliases and literal names are found by binary search, and
+//! `*.suffix` patterns sit in a tree keyed by the characters of the suffix from its end.
+//!
+//! The file is read into memory rather than mapped: mapping needs `unsafe` (and breaks if the
+//! file is truncated while mapped), and at a few hundred KiB, one read costs next to nothing.
+
+use {
+    crate::cache_file::{CacheFile, offset},
+    std::{cmp::Ordering, path::Path},
+};
+
+/// Text files generated along with the cache. If any of them is newer, the cache is stale.
+pub const SOURCES: [&str; 6] = [
+    "globs2",
+    "magic",
+    "aliases",
+    "subclasses",
+    "icons",
+    "generic-icons",
+];
+
+/// Offsets of the lists in the header
+const ALIASES: u32 = 4;
+const PARENTS: u32 = 8;
+const LITERALS: u32 = 12;
+const SUFFIXES: u32 = 16;
+const GLOBS: u32 = 20;
+const MAGIC: u32 = 24;
+const ICONS: u32 = 32;
+const GENERIC_ICONS: u32 = 36;
+
+/// Flag of a weight marking a case-sensitive pattern
+const CASE_SENSITIVE: u32 = 0x100;
+
+/// Nesting of magic matchlets we follow at most, in case of a corrupt file
+const MAX_DEPTH: usize = 32;
+
+pub struct MimeCache {
+    data: CacheFile,
+}
+
+/// What stays the same while walking the suffix tree
+struct SuffixLookup {
+    /// Length of the whole name in bytes
+    name_len: usize,
+    skip_case_sensitive: bool,
+}
+
+/// A literal, suffix or glob pattern of a type
+pub struct Pattern<'a> {
+    pub mime: &'a str,
+    pub weight: u32,
+    pub case_sensitive: bool,
+}
+
+impl MimeCache {
+    /// The cache of the mime directory `dir`, unless it's missing, stale or of an unknown version
+    pub fn open(dir: &Path) -> Option<Self> {
+        let path = dir.join("mime.cache");
+        let modified = path.metadata().ok()?.modified().ok()?;
+        let stale = SOURCES
+            .iter()
+            .filter_map(|file| dir.join(file).metadata().ok()?.modified().ok())
+            .any(|source| source > modified);
+        if stale {
+            return None;
+        }
+        let cache = Self {
+            data: CacheFile::read(&path)?,
+        };
+        (cache.data.u16_at(0)? == 1 && cache.data.u16_at(2)? == 2).then_some(cache)
+    }
+
+    /// The canonical type of the alias `mime`
+    pub fn unalias(&self, mime: &str) -> Option<&str> {
+        self.lookup_pair(ALIASES, mime)
+    }
+
+    pub fn icon(&self, mime: &str) -> Option<&str> {
+        self.lookup_pair(ICONS, mime)
+    }
+
+    pub fn generic_icon(&self, mime: &str) -> Option<&str> {
+        self.lookup_pair(GENERIC_ICONS, mime)
+    }
+
+    /// The types `mime` is a direct subclass of
+    pub fn parents(&self, mime: &str) -> Vec<&str> {
+        let parents = || -> Option<Vec<&str>> {
+            let list = self.data.u32_at(PARENTS)?;
+            let entry = self.equal_range(list, 8, mime).next()?;
+            let parents = self.data.u32_at(entry.checked_add(4)?)?;
+            let count = self
+                .data
+                .entries(parents.checked_add(4)?, self.data.u32_at(parents)?, 4);
+            (0..count)
+                .map(|i| {
+                    self.data
+                        .str_at(self.data.u32_at(offset(parents, 4, i, 4)?)?)
+                })
+                .collect()
+        };
+        parents().unwrap_or_default()
+    }
+
+    /// Entries of the literal file name list for exactly `name`
+    pub fn literals<'a>(&'a self, name: &'a str) -> impl Iterator<Item = Pattern<'a>> {
+        let list = self.data.u32_at(LITERALS);
+        list.into_iter()
+            .flat_map(move |list| self.equal_range(list, 12, name))
+            .filter_map(|entry| self.pattern(entry.checked_add(4)?))
+    }
+
+    /// Types of the longest `*.suffix` patterns matching `name`, each with the length of its
+    /// suffix. Case-sensitive patterns are left out with `skip_case_sensitive`.
+    pub fn suffixes(&self, name: &str, skip_case_sensitive: bool) -> Vec<(Pattern<'_>, usize)> {
+        let chars: Vec<char> = name.chars().collect();
+        let mut found = Vec::new();
+        if let Some(tree) = self.data.u32_at(SUFFIXES)
+            && let Some(roots) = self.data.u32_at(tree)
+            && let Some(first) = tree.checked_add(4).and_then(|at| self.data.u32_at(at))
+        {
+            let lookup = SuffixLookup {
+                name_len: name.len(),
+                skip_case_sensitive,
+            };
+            self.suffix_node(
+                self.data.entries(first, roots, 12),
+                first,
+                &chars,
+                &lookup,
+                &mut found,
+            );
+        }
+        found
+    }
+
+    /// Look up the last of `chars` among the `count` nodes at `first`, then the others among
+    /// its children. Leaves come first among children, with the character 0.
+    fn suffix_node<'a>(
+        &'a self,
+        count: u32,
+        first: u32,
+        chars: &[char],
+        lookup: &SuffixLookup,
+        found: &mut Vec<(Pattern<'a>, usize)>,
+    ) {
+        let Some((&last, rest)) = chars.split_last() else {
+            return;
+        };
+        let Some(node) = self.binary_search(first, count, 12, |node| {
+            self.data.u32_at(node).map(|c| c.cmp(&u32::from(last)))
+        }) else {
+            return;
+        };
+        let (Some(children), Some(first_child)) = (
+            node.checked_add(4).and_then(|at| self.data.u32_at(at)),
+            node.checked_add(8).and_then(|at| self.data.u32_at(at)),
+        ) else {
+            return;
+        };
+        let before = found.len();
+        let children = self.data.entries(first_child, children, 12);
+        self.suffix_node(children, first_child, rest, lookup, found);
+        // A longer suffix matched, which wins
+        if found.len() > before {
+            return;
+        }
+        let suffix_len = lookup.name_len - rest.iter().map(|c| c.len_utf8()).sum::<usize>();
+        for i in 0..children {
+            let Some(child) = offset(first_child, 0, i, 12) else {
+                break;
+            };
+            if self.data.u32_at(child) != Some(0) {
+                break;
+            }
+            if let Some(pattern) = child.checked_add(4).and_then(|at| self.pattern(at))
+                && !(lookup.skip_case_sensitive && pattern.case_sensitive)
+            {
+                found.push((pattern, suffix_len));
+            }
+        }
+    }
+
+    /// Every entry of the list of patterns that aren't literals or suffixes, with the pattern
+    pub fn globs(&self) -> impl Iterator<Item = (&str, Pattern<'_>)> {
+        let list = self.data.u32_at(GLOBS);
+        let count = list
+            .and_then(|list| {
+                Some(
+                    self.data
+                        .entries(list.checked_add(4)?, self.data.u32_at(list)?, 12),
+                )
+            })
+            .unwrap_or(0);
+        (0..count).filter_map(move |i| {
+            let entry = offset(list?, 4, i, 12)?;
+            Some((
+                self.data.str_at(self.data.u32_at(entry)?)?,
+                self.pattern(entry.checked_add(4)?)?,
+            ))
+        })
+    }
+
+    /// How many bytes from the start of a file the magic rules look at
+    pub fn magic_extent(&self) -> usize {
+        self.data
+            .u32_at(MAGIC)
+            .and_then(|magic| self.data.u32_at(magic.checked_add(4)?))
+            .unwrap_or(0) as usize
+    }
+
+    /// Types whose magic rules match `data`, with their priority, highest priority first
+    pub fn magic_matches<'a>(&'a self, data: &'a [u8]) -> impl Iterator<Item = (u32, &'a str)> {
+        let magic = self.data.u32_at(MAGIC);
+        let count = magic.and_then(|magic| self.data.u32_at(magic)).unwrap_or(0);
+        let first = magic
+            .and_then(|magic| self.data.u32_at(magic.checked_add(8)?))
+            .unwrap_or(0);
+        let count = self.data.entries(first, count, 16);
+        (0..count).filter_map(move |i| {
+            let rule = offset(first, 0, i, 16)?;
+            let field = |n: u32| self.data.u32_at(rule.checked_add(4 * n)?);
+            let priority = field(0)?;
+            let mime = self.data.str_at(field(1)?)?;
+            let (matchlets, first_matchlet) = (field(2)?, field(3)?);
+            self.any_matchlet(matchlets, first_matchlet, data, 0)
+                .then_some((priority, mime))
+        })
+    }
+
+    /// Whether any of the `count` matchlets at `first` matches, along with one of its children
+    fn any_matchlet(&self, count: u32, first: u32, data: &[u8], depth: usize) -> bool {
+        depth < MAX_DEPTH
+            && (0..self.data.entries(first, count, 32)).any(|i| {
+                let Some(matchlet) = offset(first, 0, i, 32) else {
+                    return false;
+                };
+                let field = |n: u32| self.data.u32_at(matchlet.checked_add(4 * n)?);
+                let (Some(children), Some(first_child)) = (field(6), field(7)) else {
+                    return false;
+                };
+                // Children are written after their parent, which also rules out cycles
+                if children != 0 && first_child <= matchlet {
+                    return false;
+                }
+                self.matchlet_matches(matchlet, data).unwrap_or(false)
+                    && (children == 0 || self.any_matchlet(children, first_child, data, depth + 1))
+            })
+    }
+
+    fn matchlet_matches(&self, matchlet: u32, data: &[u8]) -> Option<bool> {
+        let field = |n: u32| self.data.u32_at(matchlet.checked_add(4 * n)?);
+        let (start, range) = (field(0)? as usize, field(1)? as usize);
+        let (word_size, len) = (field(2)? as usize, field(3)? as usize);
+        let value = self.data.bytes_at(field(4)?, len)?;
+        let mask = match field(5)? {
+            0 => None,
+            at => Some(self.data.bytes_at(at, len)?),
+        };
+        // Like in the `magic` file, values are big endian but words compare in host byte order
+        let swap = cfg!(target_endian = "little") && word_size > 1;
+        let whole_words = if swap { len - len % word_size } else { 0 };
+        let host = |i: usize| {
+            if i < whole_words {
+                i - i % word_size + word_size - 1 - i % word_size
+            } else {
+                i
+            }
+        };
+        // Past the last place the value fits, nothing matches
+        let end = start
+            .checked_add(range.max(1))?
+            .min((data.len() + 1).saturating_sub(len));
+        Some((start..end).any(|at| {
+            let window = &data[at..at + len];
+            window.iter().enumerate().all(|(i, byte)| {
+                let mask = mask.map_or(0xff, |mask| mask[host(i)]);
+                byte & mask == value[host(i)] & mask
+            })
+        }))
+    }
+
+    /// The value for `key` in a sorted list of `key`, value string offset pairs
+    fn lookup_pair(&self, list: u32, key: &str) -> Option<&str> {
+        let list = self.data.u32_at(list)?;
+        let entry = self.equal_range(list, 8, key).next()?;
+        self.data.str_at(self.data.u32_at(entry.checked_add(4)?)?)
+    }
+
+    /// The type, weight and flags of a pattern whose type offset is at `offset`
+    fn pattern(&self, at: u32) -> Option<Pattern<'_>> {
+        let weight = self.data.u32_at(at.checked_add(4)?)?;
+        Some(Pattern {
+            mime: self.data.str_at(self.data.u32_at(at)?)?,
+            weight: weight & 0xff,
+            case_sensitive: weight & CASE_SENSITIVE != 0,
+        })
+    }
+
+    /// Offsets of the entries whose key equals `key`, in the sorted list at `list` whose
+    /// entries are `size` bytes and start with the offset of their key
+    fn equal_range(&self, list: u32, size: u32, key: &str) -> impl Iterator<Item = u32> {
+        let count = list
+            .checked_add(4)
+            .and_then(|first| Some(self.data.entries(first, self.data.u32_at(list)?, size)))
+            .unwrap_or(0);
+        let key_at = move |i: u32| {
+            self.data
+                .str_at(self.data.u32_at(offset(list, 4, i, size)?)?)
+        };
+        // The first entry not less than `key`
+        let (mut low, mut high) = (0, count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match key_at(mid) {
+                Some(entry) if entry < key => low = mid + 1,
+                _ => high = mid,
+            }
+        }
+        (low..count)
+            .take_while(move |&i| key_at(i) == Some(key))
+            .filter_map(move |i| offset(list, 4, i, size))
+    }
+
+    /// The entry of the sorted list at `first` that `compare` finds equal
+    fn binary_search(
+        &self,
+        first: u32,
+        count: u32,
+        size: u32,
+        compare: impl Fn(u32) -> Option<Ordering>,
+    ) -> Option<u32> {
+        let (mut low, mut high) = (0, count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let entry = offset(first, 0, mid, size)?;
+            match compare(entry)? {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Some(entry),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, crate::cache_file::for_each_damaged, std::collections::BTreeMap};
+
+    /// Writes a `mime.cache` the way `update-mime-database` lays it out
+    struct Builder {
+        data: Vec<u8>,
+    }
+
+    /// Suffixes by their reversed characters
+    #[derive(Default)]
+    struct Trie {
+        children: BTreeMap<char, Trie>,
+        leaves: Vec<(&'static str, u32)>,
+    }
+
+    struct Matchlet {
+        offset: u32,
+        range: u32,
+        word_size: u32,
+        value: &'static [u8],
+        mask: Option<&'static [u8]>,
+        children: Vec<Matchlet>,
+    }
+
+    impl Builder {
+        fn new() -> Self {
+            let mut data = vec![0; 40];
+            data[..4].copy_from_slice(&[0, 1, 0, 2]);
+            Self { data }
+        }
+
+        fn here(&self) -> u32 {
+            self.data.len() as u32
+        }
+
+        fn push(&mut self, values: &[u32]) {
+            for value in values {
+                self.data.extend(value.to_be_bytes());
+            }
+        }
+
+        fn patch(&mut self, at: u32, value: u32) {
+            let at = at as usize;
+            self.data[at..at + 4].copy_from_slice(&value.to_be_bytes());
+        }
+
+        /// Reserve `len` bytes, returning their offset
+        fn reserve(&mut self, len: usize) -> u32 {
+            let at = self.here();
+            self.data.resize(self.data.len() + len, 0);
+            at
+        }
+
+        fn string(&mut self, s: &str) -> u32 {
+            let at = self.here();
+            self.data.extend(s.as_bytes());
+            self.data.push(0);
+            at
+        }
+
+        /// A sorted list of string tuples, pointed to by the header field at `header`
+        fn list(&mut self, header: u32, mut entries: Vec<Vec<&str>>) {
+            entries.sort();
+            let offsets: Vec<Vec<u32>> = entries
+                .iter()
+                .map(|entry| entry.iter().map(|s| self.string(s)).collect())
+                .collect();
+            let list = self.here();
+            self.push(&[offsets.len() as u32]);
+            for entry in offsets {
+                self.push(&entry);
+            }
+            self.patch(header, list);
+        }
+
+        /// `(pattern, type, weight)` entries, sorted by pattern
+        fn patterns(&mut self, header: u32, mut entries: Vec<(&str, &str, u32)>) {
+            entries.sort();
+            let offsets: Vec<[u32; 3]> = entries
+                .iter()
+                .map(|(pattern, mime, weight)| [self.string(pattern), self.string(mime), *weight])
+                .collect();
+            let list = self.here();
+            self.push(&[offsets.len() as u32]);
+            for entry in offsets {
+                self.push(&entry);
+            }
+            self.patch(header, list);
+        }
+
+        /// Types with their parents, sorted by type
+        fn parents(&mut self, entries: &[(&str, &[&str])]) {
+            let mut entries = entries.to_vec();
+            entries.sort();
+            let lists: Vec<(u32, u32)> = entries
+                .iter()
+                .map(|(mime, parents)| {
+                    let mime = self.string(mime);
+                    let parents: Vec<u32> = parents.iter().map(|p| self.string(p)).collect();
+                    let list = self.here();
+                    self.push(&[parents.len() as u32]);
+                    self.push(&parents);
+                    (mime, list)
+                })
+                .collect();
+            let list = self.here();
+            self.push(&[lists.len() as u32]);
+            for (mime, parents) in lists {
+                self.push(&[mime, parents]);
+            }
+            self.patch(PARENTS, list);
+        }
+
+        /// `*suffix` patterns with their type and weight
+        fn suffixes(&mut self, entries: &[(&str, &'static str, u32)]) {
+            let mut root = Trie::default();
+            for (suffix, mime, weight) in entries {
+                let node = suffix
+                    .chars()
+                    .rev()
+                    .fold(&mut root, |node, c| node.children.entry(c).or_default());
+                node.leaves.push((mime, *weight));
+            }
+            let (count, first) = self.level(&root);
+            let tree = self.here();
+            self.push(&[count, first]);
+            self.patch(SUFFIXES, tree);
+        }
+
+        /// The children of `trie` as one array of nodes, leaves first
+        fn level(&mut self, trie: &Trie) -> (u32, u32) {
+            let count = trie.leaves.len() + trie.children.len();
+            let first = self.reserve(12 * count);
+            for (i, (mime, weight)) in trie.leaves.iter().enumerate() {
+                let mime = self.string(mime);
+                let node = first + 12 * i as u32;
+                self.patch(node + 4, mime);
+                self.patch(node + 8, *weight);
+            }
+            for (i, (c, child)) in trie.children.iter().enumerate() {
+                let (children, first_child) = self.level(child);
+                let node = first + 12 * (trie.leaves.len() + i) as u32;
+                self.patch(node, u32::from(*c));
+                self.patch(node + 4, children);
+                self.patch(node + 8, first_child);
+            }
+            (count as u32, first)
+        }
+
+        /// Rules of `(priority, type, matchlets)`, highest priority first
+        fn magic(&mut self, extent: u32, rules: &[(u32, &str, Vec<Matchlet>)]) {
+            let rules: Vec<[u32; 4]> = rules
+                .iter()
+                .map(|(priority, mime, matchlets)| {
+                    let mime = self.string(mime);
+                    let (count, first) = self.matchlets(matchlets);
+                    [*priority, mime, count, first]
+                })
+                .collect();
+            let first = self.here();
+            for rule in &rules {
+                self.push(rule);
+            }
+            let magic = self.here();
+            self.push(&[rules.len() as u32, extent, first]);
+            self.patch(MAGIC, magic);
+        }
+
+        fn matchlets(&mut self, matchlets: &[Matchlet]) -> (u32, u32) {
+            let first = self.reserve(32 * matchlets.len());
+            for (i, m) in matchlets.iter().enumerate() {
+                let value = self.reserve(m.value.len());
+                self.data[value as usize..][..m.value.len()].copy_from_slice(m.value);
+                let mask = match m.mask {
+                    Some(mask) => {
+                        let at = self.reserve(mask.len());
+                        self.data[at as usize..][..mask.len()].copy_from_slice(mask);
+                        at
+                    }
+                    None => 0,
+                };
+                let (children, first_child) = self.matchlets(&m.children);
+                let fields = [
+                    m.offset,
+                    m.range,
+                    m.word_size,
+                    m.value.len() as u32,
+                    value,
+                    mask,
+                    children,
+                    first_child,
+                ];
+                for (n, field) in fields.into_iter().enumerate() {
+                    self.patch(first + 32 * i as u32 + 4 * n as u32, field);
+                }
+            }
+            (matchlets.len() as u32, first)
+        }
+    }
+
+    fn matchlet(offset: u32, value: &'static [u8]) -> Matchlet {
+        Matchlet {
+            offset,
+            range: 1,
+            word_size: 1,
+            value,
+            mask: None,
+            children: Vec::new(),
+        }
+    }
+
+    fn fixture() -> Vec<u8> {
+        let mut b = Builder::new();
+        b.list(
+            ALIASES,
+            vec![
+                vec!["application/x-gzip", "application/gzip"],
+                vec!["application/x-pdf", "application/pdf"],
+            ],
+        );
+        b.parents(&[
+            ("application/x-compressed-tar", &["application/gzip"]),
+            ("text/x-csrc", &["text/plain"]),
+            ("text/x-c++src", &["text/x-csrc", "text/plain"]),
+        ]);
+        b.patterns(
+            LITERALS,
+            vec![
+                ("Makefile", "text/x-makefile", 50),
+                ("core", "application/x-core", 50 | CASE_SENSITIVE),
+            ],
+        );
+        b.suffixes(&[
+            (".gz", "application/gzip", 50),
+            (".tar.gz", "application/x-compressed-tar", 50),
+            (".c", "text/x-csrc", 50),
+            (".C", "text/x-c++src", 50 | CASE_SENSITIVE),
+        ]);
+        b.patterns(GLOBS, vec![("README*", "text/x-readme", 10)]);
+        b.magic(
+            15,
+            &[
+                (
+                    80,
+                    "application/x-masked",
+                    vec![Matchlet {
+                        mask: Some(b"\xf0\xf0"),
+                        ..matchlet(0, b"\xf0\x0f")
+                    }],
+                ),
+                (
+                    60,
+                    "application/x-word",
+                    vec![Matchlet {
+                        word_size: 2,
+                        ..matchlet(4, b"\x12\x34")
+                    }],
+                ),
+                (
+                    50,
+                    "application/x-nested",
+                    vec![Matchlet {
+                        children: vec![Matchlet {
+                            range: 5,
+                            ..matchlet(10, b"x")
+                        }],
+                        ..matchlet(0, b"abc")
+                    }],
+                ),
+            ],
+        );
+        b.list(
+            ICONS,
+            vec![vec!["application/gzip", "package-x-compressed"]],
+        );
+        b.list(
+            GENERIC_ICONS,
+            vec![vec!["application/gzip", "package-x-generic"]],
+        );
+        b.data
+    }
+
+    fn cache() -> MimeCache {
+        MimeCache {
+            data: CacheFile::new(fixture()),
+        }
+    }
+
+    #[test]
+    fn aliases_and_icons() {
+        let cache = cache();
+        assert_eq!(
+            cache.unalias("application/x-gzip"),
+            Some("application/gzip")
+        );
+        assert_eq!(cache.unalias("application/x-pdf"), Some("application/pdf"));
+        assert_eq!(cache.unalias("application/gzip"), None);
+        assert_eq!(cache.icon("application/gzip"), Some("package-x-compressed"));
+        assert_eq!(
+            cache.generic_icon("application/gzip"),
+            Some("package-x-generic")
+        );
+        assert_eq!(cache.icon("text/plain"), None);
+    }
+
+    #[test]
+    fn parents() {
+        let cache = cache();
+        assert_eq!(
+            cache.parents("text/x-c++src"),
+            ["text/x-csrc", "text/plain"]
+        );
+        assert_eq!(
+            cache.parents("application/x-compressed-tar"),
+            ["application/gzip"]
+        );
+        assert!(cache.parents("text/plain").is_empty());
+    }
+
+    #[test]
+    fn literals() {
+        let cache = cache();
+        let found: Vec<_> = cache
+            .literals("Makefile")
+            .map(|p| (p.mime, p.weight, p.case_sensitive))
+            .collect();
+        assert_eq!(found, [("text/x-makefile", 50, false)]);
+        let found: Vec<_> = cache.literals("core").map(|p| p.case_sensitive).collect();
+        assert_eq!(found, [true]);
+        assert_eq!(cache.literals("makefile").count(), 0);
+    }
+
+    fn suffixes(cache: &MimeCache, name: &str, skip_cs: bool) -> Vec<(String, usize)> {
+        cache
+            .suffixes(name, skip_cs)
+            .into_iter()
+            .map(|(p, len)| (p.mime.to_string(), len))
+            .collect()
+    }
+
+    #[test]
+    fn longest_suffix_wins() {
+        let cache = cache();
+        assert_eq!(
+            suffixes(&cache, "a.tar.gz", false),
+            [("application/x-compressed-tar".to_string(), 7)]
+        );
+        assert_eq!(
+            suffixes(&cache, "a.gz", false),
+            [("application/gzip".to_string(), 3)]
+        );
+        assert!(suffixes(&cache, "a.targz", false).is_empty());
+        assert!(suffixes(&cache, "gz", false).is_empty());
+    }
+
+    #[test]
+    fn case_sensitive_suffixes() {
+        let cache = cache();
+        let found = cache.suffixes("x.C", false);
+        assert_eq!(found.len(), 1);
+        let (pattern, _) = &found[0];
+        assert_eq!(
+            (pattern.mime, pattern.weight, pattern.case_sensitive),
+            ("text/x-c++src", 50, true)
+        );
+        // The lowercased name only matches patterns that ignore case
+        assert_eq!(
+            suffixes(&cache, "x.c", true),
+            [("text/x-csrc".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn globs() {
+        let found: Vec<_> = cache()
+            .globs()
+            .map(|(glob, p)| (glob.to_string(), p.mime.to_string(), p.weight))
+            .collect();
+        assert_eq!(
+            found,
+            [("README*".to_string(), "text/x-readme".to_string(), 10)]
+        );
+    }
+
+    fn magic(data: &[u8]) -> Vec<(u32, String)> {
+        cache()
+            .magic_matches(data)
+            .map(|(priority, mime)| (priority, mime.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn magic_masks_words_and_children() {
+        assert_eq!(cache().magic_extent(), 15);
+        assert_eq!(magic(b"\xfa\x05"), [(80, "application/x-masked".into())]);
+        assert!(magic(b"\x0a\x05").is_empty());
+        let mut data = b"____".to_vec();
+        data.extend(0x1234u16.to_ne_bytes());
+        assert_eq!(magic(&data), [(60, "application/x-word".into())]);
+        assert_eq!(
+            magic(b"abc___________x"),
+            [(50, "application/x-nested".into())]
+        );
+        assert!(magic(b"abc____________x").is_empty());
+        assert!(magic(b"abc").is_empty());
+    }
+
+    #[test]
+    fn open_checks_staleness_and_version() {
+        let dir = std::env::temp_dir().join(format!("mime-cache-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("globs2"), "").unwrap();
+        // Older than the cache, whatever the file system's time granularity
+        let old = std::time::SystemTime::now() - std::time::Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(dir.join("globs2"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        std::fs::write(dir.join("mime.cache"), fixture()).unwrap();
+        assert!(MimeCache::open(&dir).is_some());
+        let mut newer = fixture();
+        newer[3] = 3;
+        std::fs::write(dir.join("mime.cache"), newer).unwrap();
+        assert!(MimeCache::open(&dir).is_none(), "version 1.3");
+        std::fs::write(dir.join("mime.cache"), fixture()).unwrap();
+        std::fs::write(dir.join("globs2"), "").unwrap();
+        let future = std::time::SystemTime::now() + std::time::Duration::from_secs(60);
+        std::fs::File::options()
+            .write(true)
+            .open(dir.join("globs2"))
+            .unwrap()
+            .set_modified(future)
+            .unwrap();
+        assert!(MimeCache::open(&dir).is_none(), "stale");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    /// Run every lookup, which must not panic whatever the data
+    fn look_up_everything(cache: &MimeCache) {
+        cache.unalias("application/x-gzip");
+        cache.icon("application/gzip");
+        cache.generic_icon("application/gzip");
+        cache.parents("text/x-c++src");
+        cache.literals("Makefile").count();
+        cache.suffixes("a.tar.gz", false);
+        cache.globs().count();
+        cache.magic_extent();
+        cache.magic_matches(b"abc___________x\xfa\x05").count();
+    }
+
+    #[test]
+    fn corrupt_offsets_find_nothing() {
+        let mut data = fixture();
+        for header in [ALIASES, PARENTS, LITERALS, SUFFIXES, GLOBS, MAGIC] {
+            data[header as usize..][..4].copy_from_slice(&(u32::MAX - 2).to_be_bytes());
+        }
+        let cache = MimeCache {
+            data: CacheFile::new(data),
+        };
+        look_up_everything(&cache);
+        assert_eq!(cache.unalias("application/x-gzip"), None);
+        assert!(cache.suffixes("a.gz", false).is_empty());
+        assert_eq!(cache.magic_matches(b"\xfa\x05").count(), 0);
+    }
+
+    #[test]
+    fn damaged_caches_dont_panic() {
+        for_each_damaged(&fixture(), |data| look_up_everything(&MimeCache { data }));
+    }
+}
@@ -0,0 +1,244 @@
+//! The `mimeapps.list` files associating MIME types with applications
+//! (see <https://specifications.freedesktop.org/mime-apps-spec/latest/>)
+
+use {
+    crate::{
+        basedir,
+        keyfile::KeyFile,
+        shared_mime,
+        xdg_desktop_file::{
+            DesktopMap, find_desktop_file, installed_desktop_files, parse_desktop_file,
+        },
+    },
+    std::path::{Path, PathBuf},
+};
+
+/// All places a `mimeapps.list` may be in, most important first.
+///
+/// Desktop specific lists (e.g. `kde-mimeapps.list`) come before the generic one
+/// in each directory.
+pub fn list_paths() -> Vec<PathBuf> {
+    let desktops = basedir::current_desktops();
+    let mut dirs = basedir::config_dirs();
+    dirs.extend(
+        basedir::data_dirs()
+            .into_iter()
+            .map(|dir| dir.join("applications")),
+    );
+    let mut paths = Vec::new();
+    for dir in dirs {
+        for desktop in &desktops {
+            paths.push(dir.join(format!("{desktop}-mimeapps.list")));
+        }
+        paths.push(dir.join("mimeapps.list"));
+    }
+    paths
+}
+
+/// Result of looking for the default application of a MIME type
+#[derive(Default)]
+pub struct DefaultLookup {
+    /// Existing files that were read, along with the desktop IDs they list as default
+    pub consulted: Vec<(PathBuf, Vec<String>)>,
+    /// The winning desktop ID, and the file it came from
+    pub winner: Option<(String, PathBuf)>,
+}
+
+/// Find the default application for `mime` from the `[Default Applications]` groups.
+///
+/// The first listed application that is actually installed wins.
+pub fn default_app(mime: &str) -> DefaultLookup {
+    let mut lookup = DefaultLookup::default();
+    for path in list_paths() {
+        let Ok(kf) = KeyFile::load(&path) else {
+            continue;
+        };
+        let ids = kf
+            .get("Default Applications", mime)
+            .map(split_list)
+            .unwrap_or_default();
+        let installed = ids
+            .iter()
+            .find(|id| find_desktop_file(id).is_some())
+            .cloned();
+        lookup.consulted.push((path.clone(), ids));
+        if let Some(id) = installed {
+            lookup.winner = Some((id, path));
+            break;
+        }
+    }
+    lookup
+}
+
+/// The application to use for `mime` when no default is set: the most preferred one
+/// associated with the type itself
+pub fn fallback_app(mime: &str) -> Option<Handler> {
+    handlers(mime)
+        .into_iter()
+        .find(|handler| handler.via.is_none())
+}
+
+/// The default application for `mime` as `xdg-mime query default` would report it: the
+/// default from the lists, or else the most preferred associated application
+pub fn effective_default(mime: &str) -> Option<String> {
+    match default_app(mime).winner {
+        Some((id, _)) => Some(id),
+        None => fallback_app(mime).map(|handler| handler.id),
+    }
+}
+
+/// Make `id` the default application for all of `mimes`.
+///
+/// This is written to the user's `mimeapps.list`, and to the desktop specific lists next to it
+/// that already exist, since those would otherwise still take precedence.
+/// Returns the paths of the files written.
+pub fn set_default(id: &str, mimes: &[impl AsRef<str>]) -> std::io::Result<Vec<PathBuf>> {
+    let dir = dirs::config_dir().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            "Couldn't determine the config directory",
+        )
+    })?;
+    let mut paths = vec![dir.join("mimeapps.list")];
+    paths.extend(
+        basedir::current_desktops()
+            .iter()
+            .map(|desktop| dir.join(format!("{desktop}-mimeapps.list")))
+            .filter(|path| path.exists()),
+    );
+    for path in &paths {
+        let mut kf = match KeyFile::load(path) {
+            Ok(kf) => kf,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => KeyFile::default(),
+            Err(err) => return Err(err),
+        };
+        for mime in mimes {
+            kf.set("Default Applications", mime.as_ref(), id);
+        }
+        std::fs::create_dir_all(&dir)?;
+        kf.save(path)?;
+    }
+    Ok(paths)
+}
+
+/// Split a `;` separated list of desktop IDs
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(';')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Where an association between a MIME type and an application comes from
+pub enum Source {
+    /// `[Default Applications]` of a `mimeapps.list`
+    Default(PathBuf),
+    /// `[Added Associations]` of a `mimeapps.list`
+    Added(PathBuf),
+    /// The `MimeType` key of the application's desktop entry
+    DesktopEntry(PathBuf),
+}
+
+impl Source {
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Default(path) | Self::Added(path) | Self::DesktopEntry(path) => path,
+        }
+    }
+
+    /// Short description of the kind of source
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Default(_) => "Default Applications",
+            Self::Added(_) => "Added Associations",
+            Self::DesktopEntry(_) => "MimeType",
+        }
+    }
+}
+
+/// An application that can open some MIME type
+pub struct Handler {
+    pub id: String,
+    pub desktop_file: PathBuf,
+    pub desktop_map: DesktopMap,
+    pub source: Source,
+    /// The parent type this application was found for, if it wasn't found for the type itself
+    pub via: Option<String>,
+}
+
+/// All installed applications that can open `mime`, most preferred first.
+///
+/// Applications for parent types (e.g. `text/plain` for `text/markdown`) come after the ones
+/// for the type itself.
+pub fn handlers(mime: &str) -> Vec<Handler> {
+    let lists: Vec<(PathBuf, KeyFile)> = list_paths()
+        .into_iter()
+        .filter_map(|path| KeyFile::load(&path).ok().map(|kf| (path, kf)))
+        .collect();
+    let entries: Vec<(String, PathBuf, DesktopMap)> = installed_desktop_files()
+        .into_iter()
+        .filter_map(|(id, path)| {
+            let map = parse_desktop_file(&path).ok()?;
+            // Hidden entries count as deleted
+            (map.get("Hidden").is_none_or(|hidden| hidden != "true")).then_some((id, path, map))
+        })
+        .collect();
+    let mut handlers = Vec::new();
+    let canonical = shared_mime::canonical(mime);
+    collect_handlers(&canonical, None, &lists, &entries, &mut handlers);
+    for parent in shared_mime::ancestors(&canonical) {
+        collect_handlers(&parent, Some(&parent), &lists, &entries, &mut handlers);
+    }
+    handlers
+}
+
+/// Add the applications associated with `mime` that aren't in `handlers` yet
+fn collect_handlers(
+    mime: &str,
+    via: Option<&str>,
+    lists: &[(PathBuf, KeyFile)],
+    entries: &[(String, PathBuf, DesktopMap)],
+    handlers: &mut Vec<Handler>,
+) {
+    let push = |id: &str, source: Source, handlers: &mut Vec<Handler>| {
+        if handlers.iter().any(|handler| handler.id == id) {
+            return;
+        }
+        let Some((_, path, map)) = entries.iter().find(|(known, ..)| known == id) else {
+            return;
+        };
+        handlers.push(Handler {
+            id: id.to_string(),
+            desktop_file: path.clone(),
+            desktop_map: map.clone(),
+            source,
+            via: via.map(str::to_string),
+        });
+    };
+    // Removals only apply to less important files, and to the desktop entries themselves
+    let mut removed: Vec<String> = Vec::new();
+    for (path, kf) in lists {
+        let get = |group| kf.get(group, mime).map(split_list).unwrap_or_default();
+        for id in get("Default Applications") {
+            if !removed.contains(&id) {
+                push(&id, Source::Default(path.clone()), handlers);
+            }
+        }
+        for id in get("Added Associations") {
+            if !removed.contains(&id) {
+                push(&id, Source::Added(path.clone()), handlers);
+            }
+        }
+        removed.extend(get("Removed Associations"));
+    }
+    for (id, path, map) in entries {
+        let handles = map
+            .get("MimeType")
+            .is_some_and(|types| types.split(';').any(|ty| ty == mime));
+        if handles && !removed.contains(id) {
+            push(id, Source::DesktopEntry(path.clone()), handlers);
+        }
+    }
+}
@@ -0,0 +1,342 @@
+//! The preview of the file being opened, shown in the confirmation window on request
+//!
+//! Nothing is read until the preview is expanded, and then only a bounded amount, so huge
+//! files don't slow the prompt down.
+
+use {
+    crate::{file_uri, shared_mime},
+    egui_sf2g::egui,
+    std::{
+        borrow::Cow,
+        fs::{File, Metadata},
+        io::Read,
+        os::unix::fs::PermissionsExt,
+        path::{Path, PathBuf},
+        time::UNIX_EPOCH,
+    },
+};
+
+/// Types egui's image loaders can show
+const IMAGE_TYPES: [&str; 3] = ["image/png", "image/jpeg", "image/svg+xml"];
+
+/// Bigger images only get the file information, decoding them would take too long
+const MAX_IMAGE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Bitmaps are decoded in full, so a small file with huge dimensions is just as bad.
+/// SVGs are rendered at the size they're shown at.
+const MAX_IMAGE_PIXELS: u64 = 4096 * 4096;
+
+/// Wider or taller images may not fit in a texture
+const MAX_IMAGE_SIDE: u32 = 8192;
+
+/// How much of a text file is read at most
+const MAX_TEXT_BYTES: u64 = 8 * 1024;
+
+const MAX_TEXT_LINES: usize = 20;
+
+/// Images are scaled down to fit a square this big
+const IMAGE_SIZE: f32 = 256.;
+
+pub struct Preview {
+    path: PathBuf,
+    mime: String,
+    /// Loaded when the preview is first expanded
+    content: Option<Content>,
+}
+
+enum Content {
+    Image,
+    Text {
+        text: String,
+        truncated: bool,
+    },
+    Info {
+        size: u64,
+        modified: Option<String>,
+        permissions: String,
+    },
+    Error(std::io::Error),
+}
+
+impl Preview {
+    /// A preview of the local file `path` of type `mime`
+    pub fn new(path: &Path, mime: &str) -> Self {
+        Self {
+            path: path.to_owned(),
+            mime: mime.to_owned(),
+            content: None,
+        }
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new("Preview")
+            .default_open(false)
+            .show(ui, |ui| {
+                let content = self
+                    .content
+                    .get_or_insert_with(|| load(&self.path, &self.mime));
+                match content {
+                    Content::Image => {
+                        ui.add(
+                            egui::Image::new(egui::ImageSource::Uri(Cow::Owned(file_uri(
+                                &self.path,
+                            ))))
+                            .max_size(egui::vec2(IMAGE_SIZE, IMAGE_SIZE)),
+                        );
+                    }
+                    Content::Text { text, truncated } => {
+                        egui::ScrollArea::both()
+                            .max_height(IMAGE_SIZE)
+                            .show(ui, |ui| {
+                                // Lines as they are, long ones scroll
+                                ui.add(
+                                    egui::Label::new(egui::RichText::new(&*text).monospace())
+                                        .extend(),
+                                );
+                                if *truncated {
+                                    ui.weak("…");
+                                }
+                            });
+                    }
+                    Content::Info {
+                        size,
+                        modified,
+                        permissions,
+                    } => {
+                        egui::Grid::new("preview_grid").show(ui, |ui| {
+                            ui.label("Size");
+                            ui.label(human_size(*size));
+                            ui.end_row();
+                            if let Some(modified) = modified {
+                                ui.label("Modified");
+                                ui.label(&*modified);
+                                ui.end_row();
+                            }
+                            ui.label("Permissions");
+                            ui.code(&*permissions);
+                            ui.end_row();
+                        });
+                    }
+                    Content::Error(err) => {
+                        ui.label(format!("Couldn't read the file: {err}"));
+                    }
+                }
+            });
+    }
+}
+
+fn load(path: &Path, mime: &str) -> Content {
+    let meta = match path.metadata() {
+        Ok(meta) => meta,
+        Err(err) => return Content::Error(err),
+    };
+    if meta.is_file() {
+        if IMAGE_TYPES.contains(&mime) && meta.len() <= MAX_IMAGE_BYTES && fits(path, mime) {
+            return Content::Image;
+        }
+        if is_text(mime)
+            && let Some(content) = load_text(path)
+        {
+            return content;
+        }
+    }
+    info(&meta)
+}
+
+/// Whether the image at `path` is small enough to decode, going by the size in its header
+fn fits(path: &Path, mime: &str) -> bool {
+    if mime == "image/svg+xml" {
+        return true;
+    }
+    let dimensions = image::ImageReader::open(path)
+        .and_then(|reader| reader.with_guessed_format())
+        .ok()
+        .and_then(|reader| reader.into_dimensions().ok());
+    dimensions.is_some_and(|(width, height)| {
+        width.max(height) <= MAX_IMAGE_SIDE
+            && u64::from(width) * u64::from(height) <= MAX_IMAGE_PIXELS
+    })
+}
+
+fn is_text(mime: &str) -> bool {
+    mime.starts_with("text/")
+        || shared_mime::ancestors(mime)
+            .iter()
+            .any(|ty| ty == "text/plain")
+}
+
+/// The first lines of the text file at `path`, unless it turns out not to be text after all
+fn load_text(path: &Path) -> Option<Content> {
+    let mut data = Vec::new();
+    File::open(path)
+        .ok()?
+        .take(MAX_TEXT_BYTES + 1)
+        .read_to_end(&mut data)
+        .ok()?;
+    let mut truncated = data.len() as u64 > MAX_TEXT_BYTES;
+    data.truncate(MAX_TEXT_BYTES as usize);
+    let text = match std::str::from_utf8(&data) {
+        Ok(text) => text,
+        // Cut off in the middle of a character
+        Err(err) if err.error_len().is_none() => {
+            std::str::from_utf8(&data[..err.valid_up_to()]).ok()?
+        }
+        Err(_) => return None,
+    };
+    let mut lines = text.lines();
+    let shown: Vec<&str> = lines.by_ref().take(MAX_TEXT_LINES).collect();
+    truncated |= lines.next().is_some();
+    Some(Content::Text {
+        text: shown.join("\n"),
+        truncated,
+    })
+}
+
+fn info(meta: &Metadata) -> Content {
+    Content::Info {
+        size: meta.len(),
+        modified: meta
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since| format_utc(since.as_secs())),
+        permissions: permissions(meta),
+    }
+}
+
+/// `size` in bytes, in the biggest unit that keeps it at least 1
+fn human_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["bytes", "KiB", "MiB", "GiB", "TiB"];
+    // Just short of the next unit would be rounded to 1024.0 of this one
+    let next_unit_at = |unit| if unit == 0 { 1024. } else { 1023.95 };
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= next_unit_at(unit) && unit + 1 < UNITS.len() {
+        value /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{size} bytes")
+    } else {
+        format!("{value:.1} {} ({size} bytes)", UNITS[unit])
+    }
+}
+
+/// `ls -l` style permissions, like `-rw-r--r--`.
+///
+/// Symbolic links have been followed to the file being opened, so they don't show up here.
+fn permissions(meta: &Metadata) -> String {
+    let mode = meta.permissions().mode();
+    let kind = if meta.is_dir() { 'd' } else { '-' };
+    let bits = (0..9).rev().map(|bit| {
+        if mode & (1 << bit) == 0 {
+            '-'
+        } else {
+            ['x', 'w', 'r'][bit % 3]
+        }
+    });
+    std::iter::once(kind).chain(bits).collect()
+}
+
+/// `secs` since the Unix epoch as `YYYY-MM-DD HH:MM UTC`
+fn format_utc(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let (hour, minute) = (secs % 86400 / 3600, secs % 3600 / 60);
+    // Days to civil date, from Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02} {hour:02}:{minute:02} UTC")
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::fs::Permissions};
+
+    #[test]
+    fn dates() {
+        for (secs, date) in [
+            (0, "1970-01-01 00:00 UTC"),
+            (951_782_400, "2000-02-29 00:00 UTC"),
+            (1_000_000_000, "2001-09-09 01:46 UTC"),
+            (2_147_483_647, "2038-01-19 03:14 UTC"),
+            (4_102_444_799, "2099-12-31 23:59 UTC"),
+        ] {
+            assert_eq!(format_utc(secs), date, "{secs}");
+        }
+    }
+
+    #[test]
+    fn sizes() {
+        for (size, shown) in [
+            (0, "0 bytes"),
+            (1023, "1023 bytes"),
+            (1024, "1.0 KiB (1024 bytes)"),
+            (1536, "1.5 KiB (1536 bytes)"),
+            (1_048_371, "1023.8 KiB (1048371 bytes)"),
+            (1_048_575, "1.0 MiB (1048575 bytes)"),
+            (1_048_576, "1.0 MiB (1048576 bytes)"),
+            (5 << 40, "5.0 TiB (5497558138880 bytes)"),
+            (2048 << 40, "2048.0 TiB (2251799813685248 bytes)"),
+        ] {
+            assert_eq!(human_size(size), shown, "{size}");
+        }
+    }
+
+    fn text_of(test: &str, data: &[u8]) -> Option<(String, bool)> {
+        let path = std::env::temp_dir().join(format!("preview-{test}-{}", std::process::id()));
+        std::fs::write(&path, data).unwrap();
+        let content = load_text(&path);
+        std::fs::remove_file(&path).unwrap();
+        match content? {
+            Content::Text { text, truncated } => Some((text, truncated)),
+            _ => panic!("not text"),
+        }
+    }
+
+    #[test]
+    fn text_is_cut_at_a_character() {
+        // `é` is 2 bytes, the second of which is past the limit
+        let mut data = vec![b'a'; MAX_TEXT_BYTES as usize - 1];
+        data.extend("é and more".as_bytes());
+        let (text, truncated) = text_of("multibyte", &data).unwrap();
+        assert_eq!(text.len(), MAX_TEXT_BYTES as usize - 1);
+        assert!(text.bytes().all(|b| b == b'a'));
+        assert!(truncated);
+    }
+
+    #[test]
+    fn text_is_cut_at_a_line() {
+        let data: String = (1..=25).map(|n| format!("line {n}\n")).collect();
+        let (text, truncated) = text_of("lines", data.as_bytes()).unwrap();
+        assert_eq!(text.lines().count(), MAX_TEXT_LINES);
+        assert!(text.ends_with("line 20"));
+        assert!(truncated);
+        let (text, truncated) = text_of("short", b"one\ntwo\n").unwrap();
+        assert_eq!(text, "one\ntwo");
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn binary_is_not_text() {
+        assert!(text_of("binary", b"PK\x03\x04\xff\xfe rest").is_none());
+    }
+
+    #[test]
+    fn permission_bits() {
+        let path = std::env::temp_dir().join(format!("preview-mode-{}", std::process::id()));
+        std::fs::write(&path, "").unwrap();
+        std::fs::set_permissions(&path, Permissions::from_mode(0o640)).unwrap();
+        let shown = permissions(&path.metadata().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(shown, "-rw-r-----");
+        let dir = permissions(&std::env::temp_dir().metadata().unwrap());
+        assert!(dir.starts_with('d'), "{dir}");
+    }
+}
@@ -0,0 +1,190 @@
+//! The `rusty-open settings` subcommand, compatible with `xdg-settings` from xdg-utils
+
+use {
+    crate::{
+        basedir,
+        cli::{self, Setting, SettingsCommand},
+        keyfile::KeyFile,
+        mimeapps,
+        xdg_desktop_file::find_desktop_file,
+    },
+    std::{io::ErrorKind, path::PathBuf},
+};
+
+/// Everything a web browser is expected to be the default for
+const BROWSER_TYPES: [&str; 4] = [
+    "x-scheme-handler/http",
+    "x-scheme-handler/https",
+    "text/html",
+    "application/xhtml+xml",
+];
+
+/// Carry out `command`, returning the exit code
+pub fn run(command: SettingsCommand) -> i32 {
+    match command {
+        SettingsCommand::List => {
+            println!("Known properties:");
+            println!("  default-url-scheme-handler    Default handler for URL scheme");
+            println!("  default-web-browser           Default web browser");
+            0
+        }
+        SettingsCommand::Get(setting) => {
+            // Like `xdg-settings`, having no default isn't an error
+            if let Some(id) = get(&setting) {
+                println!("{id}");
+            }
+            0
+        }
+        SettingsCommand::Check(setting, id) => {
+            let is_set = get(&setting).is_some_and(|current| current == id);
+            println!("{}", if is_set { "yes" } else { "no" });
+            0
+        }
+        SettingsCommand::Set(setting, id) => set(&setting, &id),
+    }
+}
+
+fn get(setting: &Setting) -> Option<String> {
+    match setting {
+        Setting::DefaultWebBrowser => {
+            kde_browser().or_else(|| mimeapps::effective_default(BROWSER_TYPES[0]))
+        }
+        Setting::DefaultUrlSchemeHandler(scheme) => {
+            mimeapps::effective_default(&format!("x-scheme-handler/{scheme}"))
+        }
+    }
+}
+
+fn set(setting: &Setting, id: &str) -> i32 {
+    if !id.ends_with(".desktop") {
+        eprintln!("rusty-open: invalid application name '{id}'");
+        return cli::EXIT_SYNTAX;
+    }
+    if find_desktop_file(id).is_none() {
+        eprintln!("rusty-open: no desktop file found for '{id}'");
+        return cli::EXIT_NOT_FOUND;
+    }
+    let mimes = match setting {
+        Setting::DefaultWebBrowser => BROWSER_TYPES.map(String::from).to_vec(),
+        Setting::DefaultUrlSchemeHandler(scheme) => {
+            if !is_scheme(scheme) {
+                eprintln!("rusty-open: invalid URL scheme '{scheme}'");
+                return cli::EXIT_SYNTAX;
+            }
+            vec![format!("x-scheme-handler/{scheme}")]
+        }
+    };
+    if let Err(err) = mimeapps::set_default(id, &mimes) {
+        eprintln!("rusty-open: Couldn't update mimeapps.list: {err}");
+        return cli::EXIT_FAILED;
+    }
+    if matches!(setting, Setting::DefaultWebBrowser)
+        && is_kde()
+        && let Err(err) = set_kde_browser(id)
+    {
+        eprintln!("rusty-open: Couldn't update kdeglobals: {err}");
+        return cli::EXIT_FAILED;
+    }
+    0
+}
+
+/// Whether `scheme` is a valid URL scheme, as described in RFC 3986
+fn is_scheme(scheme: &str) -> bool {
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"+-.".contains(&b))
+}
+
+pub fn is_kde() -> bool {
+    basedir::current_desktops()
+        .iter()
+        .any(|desktop| desktop == "kde")
+}
+
+fn kdeglobals_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("kdeglobals"))
+}
+
+/// The browser Plasma uses for links, which it keeps apart from `mimeapps.list`
+fn kde_browser() -> Option<String> {
+    if !is_kde() {
+        return None;
+    }
+    let kf = KeyFile::load(kdeglobals_path()?).ok()?;
+    let browser = kf.get("General", "BrowserApplication")?;
+    // A leading `!` marks a command rather than a desktop file, which we can't report
+    if browser.is_empty() || browser.starts_with('!') {
+        return None;
+    }
+    Some(if browser.ends_with(".desktop") {
+        browser.to_string()
+    } else {
+        format!("{browser}.desktop")
+    })
+}
+
+fn set_kde_browser(id: &str) -> std::io::Result<()> {
+    let path = kdeglobals_path().ok_or(ErrorKind::NotFound)?;
+    let mut kf = match KeyFile::load(&path) {
+        Ok(kf) => kf,
+        Err(err) if err.kind() == ErrorKind::NotFound => KeyFile::default(),
+        Err(err) => return Err(err),
+    };
+    set_browser_entry(&mut kf, id);
+    kf.save(&path)
+}
+
+/// Point `BrowserApplication` in `kdeglobals` to `id`, leaving the rest of the file alone
+fn set_browser_entry(kf: &mut KeyFile, id: &str) {
+    // An expanded (`[$e]`) entry would shadow ours, so it becomes ours instead
+    kf.rename("General", "BrowserApplication[$e]", "BrowserApplication");
+    kf.set("General", "BrowserApplication", id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KDEGLOBALS: &str = "\
+[$Version]
+update_info=filepicker.upd:filepicker-remove-old-previews-entry
+
+[General]
+AccentColor=61,174,233
+BrowserApplication[$e]=!firefox
+ColorScheme=BreezeDark
+# set by hand
+TerminalApplication=konsole
+
+[Icons]
+Theme=breeze-dark
+";
+
+    #[test]
+    fn browser_entry_replaces_only_its_line() {
+        let mut kf = KeyFile::parse(KDEGLOBALS);
+        set_browser_entry(&mut kf, "org.mozilla.firefox.desktop");
+        assert_eq!(
+            kf.text(),
+            KDEGLOBALS.replace(
+                "BrowserApplication[$e]=!firefox",
+                "BrowserApplication=org.mozilla.firefox.desktop"
+            )
+        );
+    }
+
+    #[test]
+    fn browser_entry_is_added_to_general() {
+        let text = KDEGLOBALS.replace("BrowserApplication[$e]=!firefox\n", "");
+        let mut kf = KeyFile::parse(&text);
+        set_browser_entry(&mut kf, "chromium.desktop");
+        assert_eq!(
+            kf.text(),
+            text.replace(
+                "TerminalApplication=konsole\n",
+                "TerminalApplication=konsole\nBrowserApplication=chromium.desktop\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,687 @@
+//! Reading the shared-mime-info database directly
+//! (see <https://specifications.freedesktop.org/shared-mime-info-spec/latest/>)
+//!
+//! The binary `mime.cache` files are used when every mime directory has an up to date one,
+//! the text files otherwise.
+
+use {
+    crate::{
+        basedir,
+        magic::{MAX_EXTENT, Magic},
+        mime_cache::{self, MimeCache},
+    },
+    std::{
+        cmp::Reverse,
+        collections::{HashMap, HashSet},
+        fs::File,
+        io::Read,
+        os::unix::fs::FileTypeExt,
+        path::{Path, PathBuf},
+        sync::OnceLock,
+    },
+};
+
+/// `mime` directories of all XDG data dirs, most important first
+pub fn mime_dirs() -> Vec<PathBuf> {
+    basedir::data_dirs()
+        .into_iter()
+        .map(|dir| dir.join("mime"))
+        .filter(|dir| dir.is_dir())
+        .collect()
+}
+
+/// The `mime.cache` of every mime directory, loaded on first access.
+///
+/// `None` if any directory with a database lacks an up to date cache, since mixing the caches
+/// of some directories with the text files of others would get their precedence wrong.
+fn caches() -> Option<&'static [MimeCache]> {
+    static CACHES: OnceLock<Option<Vec<MimeCache>>> = OnceLock::new();
+    CACHES
+        .get_or_init(|| {
+            mime_dirs()
+                .iter()
+                .filter(|dir| {
+                    std::iter::once("mime.cache")
+                        .chain(mime_cache::SOURCES)
+                        .any(|file| dir.join(file).exists())
+                })
+                .map(|dir| MimeCache::open(dir))
+                .collect()
+        })
+        .as_deref()
+}
+
+/// A `weight:type:pattern[:flags]` line of `globs2`
+struct Glob {
+    weight: u32,
+    mime: String,
+    pattern: String,
+    case_sensitive: bool,
+}
+
+/// The globs of all mime directories, most important directory first.
+///
+/// A `__NOGLOBS__` pattern drops the globs of that type from less important directories.
+fn globs() -> Vec<Glob> {
+    let mut globs = Vec::new();
+    let mut no_globs: Vec<String> = Vec::new();
+    for dir in mime_dirs() {
+        let Ok(text) = std::fs::read_to_string(dir.join("globs2")) else {
+            continue;
+        };
+        let mut dir_no_globs = Vec::new();
+        for line in text.lines().filter(|line| !line.starts_with('#')) {
+            let mut fields = line.split(':');
+            let (Some(weight), Some(mime), Some(pattern)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            if pattern == "__NOGLOBS__" {
+                dir_no_globs.push(mime.to_string());
+                continue;
+            }
+            if no_globs.iter().any(|ty| ty == mime) {
+                continue;
+            }
+            globs.push(Glob {
+                weight: weight.parse().unwrap_or(50),
+                mime: mime.to_string(),
+                pattern: pattern.to_string(),
+                case_sensitive: fields.next().is_some_and(|flags| flags.contains("cs")),
+            });
+        }
+        no_globs.extend(dir_no_globs);
+    }
+    globs
+}
+
+/// The extension most commonly used for files of type `mime`, without the leading dot.
+///
+/// This is the extension of the highest weighted simple `*.ext` glob in `globs2`.
+pub fn preferred_extension(mime: &str) -> Option<String> {
+    let mut best: Option<(u32, &str)> = None;
+    let globs = globs();
+    for glob in globs.iter().filter(|glob| glob.mime == mime) {
+        let Some(ext) = glob.pattern.strip_prefix("*.") else {
+            continue;
+        };
+        if ext.contains(['*', '?', '[']) {
+            continue;
+        }
+        // On equal weights, the first glob listed wins
+        if best
+            .as_ref()
+            .is_none_or(|(best_weight, _)| glob.weight > *best_weight)
+        {
+            best = Some((glob.weight, ext));
+        }
+    }
+    best.map(|(_, ext)| ext.to_string())
+}
+
+/// Detect the type of the local file `path`, along with how it was detected.
+///
+/// Follows the recommended checking order of the spec: file names are matched against the
+/// globs first, and the content is only looked at if that gives no single answer.
+/// Returns `None` if there is no MIME database at all.
+pub fn detect(path: &Path) -> Option<(String, &'static str)> {
+    let dirs = mime_dirs();
+    if dirs.is_empty() {
+        return None;
+    }
+    if let Ok(meta) = path.metadata() {
+        let ty = meta.file_type();
+        let special = if ty.is_dir() {
+            Some("inode/directory")
+        } else if ty.is_char_device() {
+            Some("inode/chardevice")
+        } else if ty.is_block_device() {
+            Some("inode/blockdevice")
+        } else if ty.is_fifo() {
+            Some("inode/fifo")
+        } else if ty.is_socket() {
+            Some("inode/socket")
+        } else {
+            None
+        };
+        if let Some(mime) = special {
+            return Some((mime.to_string(), "file type"));
+        }
+    }
+    let (globs, magic) = match caches() {
+        Some(_) => ("mime.cache globs", "mime.cache magic"),
+        None => ("globs2", "magic"),
+    };
+    let by_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map(glob_matches)
+        .unwrap_or_default();
+    if let [mime] = by_name.as_slice() {
+        return Some((mime.clone(), globs));
+    }
+    let (data, sniffed) = sniff(path, &dirs);
+    let mut sniffed = sniffed.into_iter();
+    // Magic settles ties between globs, but doesn't override a glob match
+    let sniffed = if by_name.is_empty() {
+        sniffed.next()
+    } else {
+        sniffed.find(|mime| by_name.contains(mime))
+    };
+    if let Some(mime) = sniffed {
+        return Some((mime, magic));
+    }
+    if let Some(mime) = by_name.first() {
+        return Some((mime.clone(), globs));
+    }
+    Some((fallback(&data).to_string(), "content"))
+}
+
+/// Type of content that's only in memory, like piped input, going by magic alone
+pub fn detect_data(data: &[u8]) -> Option<(String, &'static str)> {
+    let dirs = mime_dirs();
+    if dirs.is_empty() {
+        return None;
+    }
+    let magic = load_magic(&dirs);
+    if let Some(mime) = magic_matches(magic.as_ref(), data).into_iter().next() {
+        let how = match magic {
+            Some(_) => "magic",
+            None => "mime.cache magic",
+        };
+        return Some((mime, how));
+    }
+    // Judged by as much as a file would be
+    let sample = &data[..data.len().min(extent(magic.as_ref()).max(512))];
+    Some((fallback(sample).to_string(), "content"))
+}
+
+/// Type of content no magic rule matches
+fn fallback(sample: &[u8]) -> &'static str {
+    if sample.is_empty() {
+        "application/x-zerosize"
+    } else if looks_like_text(sample) {
+        "text/plain"
+    } else {
+        "application/octet-stream"
+    }
+}
+
+/// The magic files, unless the caches are up to date and have the rules already
+fn load_magic(dirs: &[PathBuf]) -> Option<Magic> {
+    match caches() {
+        Some(_) => None,
+        None => Some(Magic::load(dirs)),
+    }
+}
+
+/// How many bytes from the start of a file the magic rules look at
+fn extent(magic: Option<&Magic>) -> usize {
+    match magic {
+        Some(magic) => magic.extent(),
+        None => caches()
+            .unwrap_or_default()
+            .iter()
+            .map(MimeCache::magic_extent)
+            .max()
+            .unwrap_or(0)
+            .min(MAX_EXTENT),
+    }
+}
+
+/// The start of the file at `path`, and the types whose magic matches it, highest priority
+/// first
+fn sniff(path: &Path, dirs: &[PathBuf]) -> (Vec<u8>, Vec<String>) {
+    let magic = load_magic(dirs);
+    let mut data = Vec::new();
+    if let Ok(file) = File::open(path) {
+        // A short read still leaves something to sniff
+        let _ = file
+            .take(extent(magic.as_ref()).max(512) as u64)
+            .read_to_end(&mut data);
+    }
+    let sniffed = magic_matches(magic.as_ref(), &data);
+    (data, sniffed)
+}
+
+/// Types whose magic matches `data`, highest priority first
+fn magic_matches(magic: Option<&Magic>, data: &[u8]) -> Vec<String> {
+    match magic {
+        Some(magic) => magic.matches(data).map(str::to_string).collect(),
+        None => {
+            let mut sniffed: Vec<(u32, &str)> = caches()
+                .unwrap_or_default()
+                .iter()
+                .flat_map(|cache| cache.magic_matches(data))
+                .collect();
+            // Stable, so more important directories still win among equal priorities
+            sniffed.sort_by_key(|&(priority, _)| Reverse(priority));
+            sniffed
+                .into_iter()
+                .map(|(_, mime)| mime.to_string())
+                .collect()
+        }
+    }
+}
+
+/// Types whose globs match `name` with the highest weight, and among those the longest pattern
+fn glob_matches(name: &str) -> Vec<String> {
+    let lower = name.to_lowercase();
+    let candidates = match caches() {
+        Some(caches) => cached_glob_matches(caches, name, &lower),
+        None => globs()
+            .into_iter()
+            .filter(|glob| {
+                let name = if glob.case_sensitive { name } else { &lower };
+                fnmatch(glob.pattern.as_bytes(), name.as_bytes())
+            })
+            .map(|glob| (glob.weight, glob.pattern.len(), glob.mime))
+            .collect(),
+    };
+    best_matches(candidates)
+}
+
+/// Types of the `(weight, pattern length, type)` candidates with the highest weight, and among
+/// those the longest pattern, in the order they're listed
+fn best_matches(candidates: Vec<(u32, usize, String)>) -> Vec<String> {
+    let mut best: Option<(u32, usize)> = None;
+    let mut matches: Vec<String> = Vec::new();
+    for (weight, len, mime) in candidates {
+        let rank = (weight, len);
+        if best.is_none_or(|best| rank > best) {
+            best = Some(rank);
+            matches.clear();
+        }
+        if Some(rank) == best && !matches.contains(&mime) {
+            matches.push(mime);
+        }
+    }
+    matches
+}
+
+/// Weight, pattern length and type of every pattern of `caches` matching `name`.
+///
+/// Like a `__NOGLOBS__` line of `globs2`, a `__NOGLOBS__` literal drops the patterns of its
+/// type from less important caches.
+fn cached_glob_matches(caches: &[MimeCache], name: &str, lower: &str) -> Vec<(u32, usize, String)> {
+    let mut found = Vec::new();
+    let mut no_globs: Vec<&str> = Vec::new();
+    for cache in caches {
+        let literals = cache
+            .literals(name)
+            .chain(cache.literals(lower).filter(|p| !p.case_sensitive))
+            .map(|pattern| (pattern, name.len()));
+        // Patterns are `*` followed by the suffix
+        let suffixes = cache
+            .suffixes(name, false)
+            .into_iter()
+            .chain(cache.suffixes(lower, true))
+            .map(|(pattern, len)| (pattern, len + 1));
+        let globs = cache.globs().filter_map(|(glob, pattern)| {
+            let name = if pattern.case_sensitive { name } else { lower };
+            fnmatch(glob.as_bytes(), name.as_bytes()).then_some((pattern, glob.len()))
+        });
+        found.extend(
+            literals
+                .chain(suffixes)
+                .chain(globs)
+                .filter(|(pattern, _)| !no_globs.contains(&pattern.mime))
+                .map(|(pattern, len)| (pattern.weight, len, pattern.mime.to_string())),
+        );
+        no_globs.extend(cache.literals("__NOGLOBS__").map(|pattern| pattern.mime));
+    }
+    found
+}
+
+/// Shell style pattern matching, with `*`, `?` and `[...]` classes
+fn fnmatch(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| fnmatch(rest, &name[skip..])),
+        Some((b'?', rest)) => !name.is_empty() && fnmatch(rest, &name[1..]),
+        Some((b'[', rest)) => {
+            let Some(end) = rest
+                .iter()
+                .skip(1)
+                .position(|&b| b == b']')
+                .map(|pos| pos + 1)
+            else {
+                // Not a class after all, just a literal `[`
+                return name.first() == Some(&b'[') && fnmatch(rest, &name[1..]);
+            };
+            let Some(&c) = name.first() else {
+                return false;
+            };
+            let (negated, class) = match rest[..end].split_first() {
+                Some((b'!' | b'^', class)) => (true, class),
+                _ => (false, &rest[..end]),
+            };
+            let mut in_class = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == b'-' {
+                    in_class |= (class[i]..=class[i + 2]).contains(&c);
+                    i += 3;
+                } else {
+                    in_class |= class[i] == c;
+                    i += 1;
+                }
+            }
+            in_class != negated && fnmatch(&rest[end + 1..], &name[1..])
+        }
+        Some((&literal, rest)) => name.first() == Some(&literal) && fnmatch(rest, &name[1..]),
+    }
+}
+
+/// Whether `data` looks like text: no control characters other than whitespace
+fn looks_like_text(data: &[u8]) -> bool {
+    // The sample may end in the middle of a multibyte character
+    let valid = match std::str::from_utf8(data) {
+        Ok(_) => true,
+        Err(err) => err.error_len().is_none(),
+    };
+    valid
+        && !data
+            .iter()
+            .any(|&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c))
+}
+
+/// Lines of `file` in all mime directories, split into two whitespace separated fields
+fn pairs(file: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    for dir in mime_dirs() {
+        let Ok(text) = std::fs::read_to_string(dir.join(file)) else {
+            continue;
+        };
+        pairs.extend(text.lines().filter_map(|line| {
+            let (a, b) = line.trim().split_once(' ')?;
+            Some((a.to_string(), b.trim().to_string()))
+        }));
+    }
+    pairs
+}
+
+/// Icon names to try for `mime`, most specific first.
+///
+/// As in the shared-mime-info spec: the type's own icon (from the `icons` files, or its name
+/// with `/` replaced by `-`), then its generic icon (from the `generic-icons` files). Those of
+/// its parent types come after that, and `<media>-x-generic` last.
+pub fn icon_names(mime: &str) -> Vec<String> {
+    ordered_icon_names(
+        mime,
+        canonical,
+        ancestors,
+        icon_table("icons", MimeCache::icon),
+        icon_table("generic-icons", MimeCache::generic_icon),
+    )
+}
+
+/// Icon names of `mime`, in the order of [`icon_names`]
+fn ordered_icon_names(
+    mime: &str,
+    canonical: impl Fn(&str) -> String,
+    ancestors: impl Fn(&str) -> Vec<String>,
+    icon: impl Fn(&str) -> Option<String>,
+    generic_icon: impl Fn(&str) -> Option<String>,
+) -> Vec<String> {
+    // Aliases have no icons of their own
+    let mime = canonical(mime);
+    let mime = mime.as_str();
+    let own_names = |ty: &str| icon(ty).into_iter().chain([ty.replace('/', "-")]);
+    let mut names: Vec<String> = own_names(mime).chain(generic_icon(mime)).collect();
+    for ty in ancestors(mime) {
+        names.extend(own_names(&ty));
+    }
+    let media = mime.split_once('/').map_or(mime, |(media, _)| media);
+    names.push(format!("{media}-x-generic"));
+    let mut seen = HashSet::new();
+    names.retain(|name| seen.insert(name.clone()));
+    names
+}
+
+/// Lookup of icons in the `file` list, through `lookup` in the caches if they're used
+fn icon_table(
+    file: &str,
+    lookup: for<'a> fn(&'a MimeCache, &str) -> Option<&'a str>,
+) -> impl Fn(&str) -> Option<String> {
+    let text = match caches() {
+        Some(_) => None,
+        None => Some(icon_files(file)),
+    };
+    move |mime| match &text {
+        Some(icons) => icons.get(mime).cloned(),
+        None => caches()
+            .unwrap_or_default()
+            .iter()
+            .find_map(|cache| lookup(cache, mime))
+            .map(str::to_string),
+    }
+}
+
+/// `type:icon` lines of `file` in all mime directories, the most important directory winning
+fn icon_files(file: &str) -> HashMap<String, String> {
+    let mut icons = HashMap::new();
+    for dir in mime_dirs().iter().rev() {
+        let Ok(text) = std::fs::read_to_string(dir.join(file)) else {
+            continue;
+        };
+        icons.extend(text.lines().filter_map(|line| {
+            let (mime, icon) = line.trim().split_once(':')?;
+            Some((mime.to_string(), icon.to_string()))
+        }));
+    }
+    icons
+}
+
+/// The canonical name of `mime`, which may be an alias (e.g. `application/x-pdf`)
+pub fn canonical(mime: &str) -> String {
+    if let Some(caches) = caches() {
+        return caches
+            .iter()
+            .find_map(|cache| cache.unalias(mime))
+            .unwrap_or(mime)
+            .to_string();
+    }
+    pairs("aliases")
+        .into_iter()
+        .find(|(alias, _)| alias == mime)
+        .map_or_else(|| mime.to_string(), |(_, canonical)| canonical)
+}
+
+/// All types `mime` is a subclass of, nearest first.
+///
+/// Besides the `subclasses` files, every `text/*` type is a `text/plain`. The implicit
+/// `application/octet-stream` parent is left out, since it would match any type.
+pub fn ancestors(mime: &str) -> Vec<String> {
+    let subclasses = match caches() {
+        Some(_) => Vec::new(),
+        None => pairs("subclasses"),
+    };
+    let direct_parents = |mime: &str| -> Vec<String> {
+        match caches() {
+            Some(caches) => caches
+                .iter()
+                .flat_map(|cache| cache.parents(mime))
+                .map(str::to_string)
+                .collect(),
+            None => subclasses
+                .iter()
+                .filter(|(child, _)| child == mime)
+                .map(|(_, parent)| parent.clone())
+                .collect(),
+        }
+    };
+    let mut ancestors: Vec<String> = Vec::new();
+    let mut queue = vec![canonical(mime)];
+    while !queue.is_empty() {
+        let current = queue.remove(0);
+        let mut parents = direct_parents(&current);
+        if current.starts_with("text/") && current != "text/plain" {
+            parents.push("text/plain".into());
+        }
+        for parent in parents {
+            if parent != mime && !ancestors.contains(&parent) {
+                ancestors.push(parent.clone());
+                queue.push(parent);
+            }
+        }
+    }
+    ancestors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, name: &str) -> bool {
+        fnmatch(pattern.as_bytes(), name.as_bytes())
+    }
+
+    /// Names of types in a small database, like that of shared-mime-info
+    fn names(mime: &str) -> Vec<String> {
+        let aliases = [("application/x-pdf", "application/pdf")];
+        let parents = [
+            ("application/x-shellscript", "application/x-executable"),
+            ("application/x-shellscript", "text/plain"),
+            ("text/markdown", "text/plain"),
+        ];
+        let icons = [
+            ("application/x-executable", "exec"),
+            ("application/x-pdf", "not-for-aliases"),
+        ];
+        let generic_icons = [
+            ("application/x-shellscript", "text-x-script"),
+            ("application/pdf", "x-office-document"),
+        ];
+        let lookup = |table: &[(&str, &str)], key: &str| {
+            table
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, v)| v.to_string())
+        };
+        ordered_icon_names(
+            mime,
+            |ty| lookup(&aliases, ty).unwrap_or_else(|| ty.to_string()),
+            |ty| {
+                parents
+                    .iter()
+                    .filter(|(child, _)| *child == ty)
+                    .map(|(_, parent)| parent.to_string())
+                    .collect()
+            },
+            |ty| lookup(&icons, ty),
+            |ty| lookup(&generic_icons, ty),
+        )
+    }
+
+    #[test]
+    fn icon_names_of_an_alias_are_those_of_its_type() {
+        assert_eq!(
+            names("application/x-pdf"),
+            [
+                "application-pdf",
+                "x-office-document",
+                "application-x-generic"
+            ]
+        );
+    }
+
+    #[test]
+    fn icon_names_of_a_text_subtype() {
+        assert_eq!(
+            names("text/markdown"),
+            ["text-markdown", "text-plain", "text-x-generic"]
+        );
+    }
+
+    #[test]
+    fn generic_icon_comes_before_parent_icons() {
+        assert_eq!(
+            names("application/x-shellscript"),
+            [
+                "application-x-shellscript",
+                "text-x-script",
+                "exec",
+                "application-x-executable",
+                "text-plain",
+                "application-x-generic",
+            ]
+        );
+    }
+
+    #[test]
+    fn fnmatch_wildcards() {
+        assert!(matches("*.txt", "notes.txt"));
+        assert!(matches("*.txt", ".txt"));
+        assert!(!matches("*.txt", "notes.txt.bak"));
+        assert!(matches("README*", "README"));
+        assert!(matches("?akefile", "Makefile"));
+        assert!(!matches("?akefile", "akefile"));
+        assert!(matches("", ""));
+        assert!(!matches("", "a"));
+    }
+
+    #[test]
+    fn fnmatch_star_backtracks() {
+        // The first `.tar` isn't the one that leads to a match
+        assert!(matches("*.tar.*", "a.tar.b.tar.gz"));
+        assert!(matches("*a*b", "xaybzab"));
+        assert!(!matches("*a*b", "xaybza"));
+        assert!(matches("**.gz", "x.gz"));
+        assert!(matches("*", ""));
+    }
+
+    #[test]
+    fn fnmatch_classes() {
+        assert!(matches("*.[ch]", "main.c"));
+        assert!(matches("*.[ch]", "main.h"));
+        assert!(!matches("*.[ch]", "main.o"));
+        assert!(matches("*.[0-9]", "ls.1"));
+        assert!(!matches("*.[0-9]", "ls.x"));
+        assert!(matches("*.[!0-9]", "ls.x"));
+        assert!(matches("*.[^0-9]", "ls.x"));
+        assert!(!matches("*.[!0-9]", "ls.1"));
+        // A `]` right after the opening bracket is part of the class
+        assert!(matches("[]]", "]"));
+        // Without a closing bracket, `[` is literal
+        assert!(matches("a[b", "a[b"));
+        assert!(!matches("[ab]", ""));
+    }
+
+    fn candidates(list: &[(u32, &str, &str)]) -> Vec<(u32, usize, String)> {
+        list.iter()
+            .map(|&(weight, pattern, mime)| (weight, pattern.len(), mime.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn best_matches_prefers_weight_over_length() {
+        let found = best_matches(candidates(&[
+            (50, "*.gz", "application/gzip"),
+            (40, "*.tar.gz", "application/x-compressed-tar"),
+        ]));
+        assert_eq!(found, ["application/gzip"]);
+    }
+
+    #[test]
+    fn best_matches_prefers_longer_patterns_on_equal_weight() {
+        let found = best_matches(candidates(&[
+            (50, "*.gz", "application/gzip"),
+            (50, "*.tar.gz", "application/x-compressed-tar"),
+        ]));
+        assert_eq!(found, ["application/x-compressed-tar"]);
+    }
+
+    #[test]
+    fn best_matches_keeps_every_tie_once() {
+        let found = best_matches(candidates(&[
+            (50, "*.ts", "video/mp2t"),
+            (50, "*.ts", "text/vnd.trolltech.linguist"),
+            (50, "*.ts", "video/mp2t"),
+            (10, "*s", "text/plain"),
+        ]));
+        assert_eq!(found, ["video/mp2t", "text/vnd.trolltech.linguist"]);
+        assert!(best_matches(Vec::new()).is_empty());
+    }
+}
@@ -0,0 +1,264 @@
+//! Deciding whether a command line argument is a local path or a URL
+
+use {
+    percent_encoding::{AsciiSet, CONTROLS, percent_decode_str, percent_encode},
+    std::{
+        borrow::Cow,
+        ffi::{OsStr, OsString},
+        os::unix::ffi::{OsStrExt, OsStringExt},
+        path::{Path, PathBuf},
+    },
+    thiserror::Error,
+    url::{Host, Url},
+};
+
+pub enum Target {
+    /// A path on the local filesystem
+    Path(PathBuf),
+    /// A `file:` URL that refers to a local path, along with the decoded path
+    FileUrl(PathBuf),
+    /// A URL to be handed to the handler of its scheme
+    Url(Url),
+    /// A bare hostname (optionally with port and path), turned into an `https:` URL
+    Host(Url),
+}
+
+#[derive(Error, Debug)]
+pub enum ClassifyError {
+    #[error("`file:` URL refers to host `{0}`, only local files can be opened")]
+    RemoteFile(String),
+}
+
+impl Target {
+    /// Human readable description of the classification
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Path(path) => format!("local path {}", path.display()),
+            Self::FileUrl(path) => format!("file: URL of local path {}", path.display()),
+            Self::Url(url) => format!("URL with scheme `{}`", url.scheme()),
+            Self::Host(url) => format!("bare hostname, opened as {url}"),
+        }
+    }
+
+    /// What should be passed to the handler application
+    pub fn launch_arg(&self, arg: &OsStr) -> OsString {
+        match self {
+            // Keep the user's spelling, `Url` normalizes things like case and trailing slashes
+            Self::Path(_) | Self::FileUrl(_) | Self::Url(_) => arg.to_owned(),
+            Self::Host(url) => url.as_str().into(),
+        }
+    }
+}
+
+/// Classify `arg`.
+///
+/// Anything that exists on disk is a path, even if it also happens to parse as a URL
+/// (e.g. `mailto:list.txt`). If `bare_hostnames` is set, arguments like `example.com/page`
+/// are treated as `https:` URLs instead of (nonexistent) paths.
+pub fn classify(arg: &OsStr, bare_hostnames: bool) -> Result<Target, ClassifyError> {
+    let path = Path::new(arg);
+    if path.symlink_metadata().is_ok() {
+        return Ok(Target::Path(path.to_owned()));
+    }
+    // A URL with raw non-UTF-8 bytes (e.g. a `file:` URL typed by hand) is not valid as-is,
+    // but percent-encoding those bytes gives the URL that was meant
+    let text = match arg.to_str() {
+        Some(text) => Cow::Borrowed(text),
+        None => Cow::Owned(percent_encode(arg.as_bytes(), NON_ASCII).to_string()),
+    };
+    let text = text.as_ref();
+    // Checked before URL parsing, because `example.com:8080` is also a valid URL,
+    // with a scheme of `example.com`
+    if bare_hostnames
+        && !text.contains("://")
+        && looks_like_hostname(text)
+        && let Ok(url) = Url::parse(&format!("https://{text}"))
+    {
+        return Ok(Target::Host(url));
+    }
+    if let Ok(url) = Url::parse(text) {
+        if url.scheme() != "file" {
+            return Ok(Target::Url(url));
+        }
+        return match url.host() {
+            None => Ok(Target::FileUrl(file_url_path(&url))),
+            Some(Host::Domain(host)) if is_local_host(host) => {
+                Ok(Target::FileUrl(file_url_path(&url)))
+            }
+            Some(host) => Err(ClassifyError::RemoteFile(host.to_string())),
+        };
+    }
+    Ok(Target::Path(path.to_owned()))
+}
+
+/// Bytes that can't appear in a URL literally
+const NON_ASCII: &AsciiSet = &CONTROLS.add(b' ');
+
+/// The local path of a `file:` URL.
+///
+/// Percent-decoding yields raw bytes, which are used as-is, since file names on Unix
+/// don't have to be UTF-8.
+fn file_url_path(url: &Url) -> PathBuf {
+    OsString::from_vec(percent_decode_str(url.path()).collect()).into()
+}
+
+/// Whether `host` in a `file:` URL refers to this machine
+fn is_local_host(host: &str) -> bool {
+    host.eq_ignore_ascii_case("localhost")
+        || gethostname::gethostname()
+            .to_str()
+            .is_some_and(|own| own.eq_ignore_ascii_case(host))
+}
+
+/// Matches `host[:port][/rest]`, where `host` is `localhost` or a dotted domain name
+/// ending in an alphabetic top level domain
+fn looks_like_hostname(text: &str) -> bool {
+    let authority = text.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = match authority.rsplit_once(':') {
+        Some((host, port)) => {
+            if port.is_empty() || !port.bytes().all(|b| b.is_ascii_digit()) {
+                return false;
+            }
+            host
+        }
+        None => authority,
+    };
+    if host.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+    let labels: Vec<&str> = host.split('.').collect();
+    let Some(tld) = labels.last() else {
+        return false;
+    };
+    labels.len() >= 2
+        && tld.len() >= 2
+        && tld.bytes().all(|b| b.is_ascii_alphabetic())
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classified(arg: impl AsRef<OsStr>, bare_hostnames: bool) -> String {
+        match classify(arg.as_ref(), bare_hostnames) {
+            Ok(target) => target.describe(),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn bare_hostnames() {
+        for (arg, expected) in [
+            (
+                "example.com:8080",
+                "bare hostname, opened as https://example.com:8080/",
+            ),
+            (
+                "example.com/page?q=1",
+                "bare hostname, opened as https://example.com/page?q=1",
+            ),
+            (
+                "localhost:3000",
+                "bare hostname, opened as https://localhost:3000/",
+            ),
+            ("example.com:http", "URL with scheme `example.com`"),
+            ("notes.v2", "local path notes.v2"),
+            ("https://example.com", "URL with scheme `https`"),
+        ] {
+            assert_eq!(classified(arg, true), expected, "{arg}");
+        }
+        // Otherwise the host is taken for a scheme, as any URL parser would
+        assert_eq!(
+            classified("example.com:8080", false),
+            "URL with scheme `example.com`"
+        );
+        assert_eq!(
+            classified("example.com/page", false),
+            "local path example.com/page"
+        );
+    }
+
+    #[test]
+    fn existing_files_win_over_urls() {
+        // Relative, since an absolute path can't parse as a URL. Tests run in the package
+        // directory.
+        let name = format!("mailto:rusty-open-test-{}", std::process::id());
+        assert_eq!(classified(&name, false), "URL with scheme `mailto`");
+        std::fs::write(&name, "").unwrap();
+        let classified = classified(&name, true);
+        std::fs::remove_file(&name).unwrap();
+        assert_eq!(classified, format!("local path {name}"));
+    }
+
+    #[test]
+    fn file_urls() {
+        for (arg, expected) in [
+            (
+                "file:///tmp/a%20b.txt",
+                "file: URL of local path /tmp/a b.txt",
+            ),
+            (
+                "file://localhost/tmp/a.txt",
+                "file: URL of local path /tmp/a.txt",
+            ),
+            (
+                "file://LocalHost/tmp/a.txt",
+                "file: URL of local path /tmp/a.txt",
+            ),
+            (
+                "file://fileserver.example.org/share/a.txt",
+                "`file:` URL refers to host `fileserver.example.org`, only local files can be \
+                 opened",
+            ),
+            (
+                "file://10.0.0.1/a.txt",
+                "`file:` URL refers to host `10.0.0.1`, only local files can be opened",
+            ),
+        ] {
+            assert_eq!(classified(arg, true), expected, "{arg}");
+        }
+        let own = gethostname::gethostname().into_string().unwrap();
+        if !own.is_empty() && own.parse::<std::net::IpAddr>().is_err() {
+            assert_eq!(
+                classified(format!("file://{own}/tmp/a.txt"), false),
+                "file: URL of local path /tmp/a.txt"
+            );
+        }
+    }
+
+    #[test]
+    fn raw_bytes_are_percent_encoded() {
+        let file_url = OsStr::from_bytes(b"file:///tmp/caf\xe9 au lait");
+        let Ok(Target::FileUrl(path)) = classify(file_url, false) else {
+            panic!("not a file: URL");
+        };
+        assert_eq!(path.as_os_str().as_bytes(), b"/tmp/caf\xe9 au lait");
+        let url = OsStr::from_bytes(b"https://example.com/caf\xe9");
+        let Ok(Target::Url(url)) = classify(url, true) else {
+            panic!("not a URL");
+        };
+        assert_eq!(url.as_str(), "https://example.com/caf%E9");
+        // And without a scheme, it's just an odd file name
+        let path = OsStr::from_bytes(b"caf\xe9.txt");
+        assert!(matches!(classify(path, false), Ok(Target::Path(p)) if p == Path::new(path)));
+    }
+
+    #[test]
+    fn launch_arg_keeps_the_spelling() {
+        let arg = OsStr::new("HTTPS://Example.com");
+        assert_eq!(classify(arg, true).unwrap().launch_arg(arg), arg);
+        let arg = OsStr::new("example.com");
+        assert_eq!(
+            classify(arg, true).unwrap().launch_arg(arg),
+            "https://example.com/"
+        );
+    }
+}
@@ -0,0 +1,93 @@
+//! Private temporary files for content that doesn't exist on disk yet
+//! (`data:` URLs, piped input), so it can be handed to applications as a path.
+//!
+//! The files can't be deleted once rusty-open exits, since the launched application may not
+//! have opened them yet. Instead, files older than [`MAX_AGE`] are swept on later runs.
+
+use std::{
+    fs::{DirBuilder, File, OpenOptions},
+    io,
+    os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt},
+    path::PathBuf,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Where our temporary files live.
+///
+/// Prefers `$XDG_RUNTIME_DIR`, which is private to the user and cleared on logout.
+fn dir_path() -> Option<PathBuf> {
+    dirs::runtime_dir()
+        .map(|dir| dir.join("rusty-open"))
+        .or_else(|| dirs::cache_dir().map(|dir| dir.join("rusty-open").join("tmp")))
+}
+
+/// Create the directory for temporary files if needed, readable only by the user
+fn dir() -> io::Result<PathBuf> {
+    let dir = dir_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No runtime or cache directory"))?;
+    DirBuilder::new().recursive(true).mode(0o700).create(&dir)?;
+    // Tighten permissions, in case the directory already existed with looser ones
+    std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+    Ok(dir)
+}
+
+/// Create a new, empty temporary file, only accessible by the user.
+///
+/// `ext` is appended as the file extension, so applications can tell the file type by name.
+pub fn create(ext: Option<&str>) -> io::Result<(File, PathBuf)> {
+    let path = dir()?.join(file_name(ext));
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?;
+    Ok((file, path))
+}
+
+/// The path [`create`] would give a file, for showing without writing anything
+pub fn new_path(ext: Option<&str>) -> io::Result<PathBuf> {
+    dir_path()
+        .map(|dir| dir.join(file_name(ext)))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No runtime or cache directory"))
+}
+
+/// A name no other temporary file has
+fn file_name(ext: Option<&str>) -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let mut name = format!("{}-{nanos}-{n}", std::process::id());
+    if let Some(ext) = ext {
+        name.push('.');
+        name.push_str(ext);
+    }
+    name
+}
+
+/// Remove temporary files left behind by earlier runs that are old enough to be unused
+pub fn sweep() {
+    let Some(dir) = dir_path() else {
+        return;
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let stale = entry
+            .metadata()
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|mtime| mtime.elapsed().ok())
+            .is_some_and(|age| age > MAX_AGE);
+        if stale {
+            // Best effort, a later run can try again
+            let _ = std::fs::remove_file(entry.path());
+        }
+    }
+}
@@ -0,0 +1,258 @@
+//! Thumbnails file managers already made, as described in the freedesktop Thumbnail Managing
+//! Standard
+//!
+//! A thumbnail is a PNG named after the MD5 of the file's URI, in a directory per size. It's
+//! only valid while its `Thumb::MTime` text matches the file's modification time.
+
+use {
+    percent_encoding::{AsciiSet, NON_ALPHANUMERIC, percent_encode},
+    std::{
+        os::unix::{ffi::OsStrExt, fs::MetadataExt},
+        path::{Path, PathBuf},
+    },
+};
+
+/// Thumbnail directories, in the order they're preferred. `normal` is 128 pixels, each next
+/// one twice as big.
+const SIZES: [&str; 4] = ["normal", "large", "x-large", "xx-large"];
+
+/// What GLib leaves unescaped in file URIs, which thumbnailers hash
+const URI_PATH: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'!')
+    .remove(b'$')
+    .remove(b'&')
+    .remove(b'\'')
+    .remove(b'(')
+    .remove(b')')
+    .remove(b'*')
+    .remove(b'+')
+    .remove(b',')
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'/')
+    .remove(b':')
+    .remove(b'=')
+    .remove(b'@')
+    .remove(b'_')
+    .remove(b'~');
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Path of an up to date thumbnail of the local file `path`, if there is one
+pub fn find(path: &Path) -> Option<PathBuf> {
+    let path = std::fs::canonicalize(path).ok()?;
+    let meta = path.metadata().ok()?;
+    if !meta.is_file() {
+        return None;
+    }
+    let root = dirs::cache_dir()?.join("thumbnails");
+    // Thumbnails aren't made of thumbnails
+    if path.starts_with(&root) {
+        return None;
+    }
+    let uri = uri(&path);
+    let name = thumbnail_name(&uri);
+    SIZES
+        .iter()
+        .map(|size| root.join(size).join(&name))
+        .find(|thumb| is_valid(thumb, &uri, meta.mtime(), meta.len()))
+}
+
+/// The URI of the absolute path `path`, as GLib writes it
+fn uri(path: &Path) -> String {
+    format!(
+        "file://{}",
+        percent_encode(path.as_os_str().as_bytes(), URI_PATH)
+    )
+}
+
+/// File name of the thumbnail of the file at `uri`
+fn thumbnail_name(uri: &str) -> String {
+    format!("{}.png", hex(&md5(uri.as_bytes())))
+}
+
+/// Whether the thumbnail at `thumb` was made from the current version of the file at `uri`
+fn is_valid(thumb: &Path, uri: &str, mtime: i64, size: u64) -> bool {
+    let Ok(data) = std::fs::read(thumb) else {
+        return false;
+    };
+    let Some(texts) = png_texts(&data) else {
+        return false;
+    };
+    let text = |key: &str| texts.iter().find(|(k, _)| *k == key).map(|(_, v)| *v);
+    // The only required field
+    if text("Thumb::MTime").and_then(|v| v.parse::<i64>().ok()) != Some(mtime) {
+        return false;
+    }
+    text("Thumb::URI").is_none_or(|v| v == uri)
+        && text("Thumb::Size")
+            .and_then(|v| v.parse::<u64>().ok())
+            .is_none_or(|v| v == size)
+}
+
+/// The keywords and values of the `tEXt` chunks of a PNG
+fn png_texts(data: &[u8]) -> Option<Vec<(&str, &str)>> {
+    let mut rest = data.strip_prefix(PNG_SIGNATURE)?;
+    let mut texts = Vec::new();
+    // Length, type, data and CRC
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes(rest[..4].try_into().ok()?) as usize;
+        let kind = &rest[4..8];
+        let chunk = rest.get(8..8 + len)?;
+        match kind {
+            b"tEXt" => {
+                let sep = chunk.iter().position(|&b| b == 0)?;
+                // Latin-1, but the fields we need are ASCII, or UTF-8 in practice
+                if let (Ok(key), Ok(value)) = (
+                    std::str::from_utf8(&chunk[..sep]),
+                    std::str::from_utf8(&chunk[sep + 1..]),
+                ) {
+                    texts.push((key, value));
+                }
+            }
+            b"IEND" => break,
+            _ => {}
+        }
+        rest = rest.get(12 + len..)?;
+    }
+    Some(texts)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// MD5 digest of `data`, as in RFC 1321. Thumbnail names are its only use, so it isn't worth
+/// a dependency.
+fn md5(data: &[u8]) -> [u8; 16] {
+    const SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+    // floor(abs(sin(i + 1)) * 2^32)
+    let k: [u32; 64] =
+        std::array::from_fn(|i| ((i as f64 + 1.).sin().abs() * 4_294_967_296.) as u32);
+    let mut state: [u32; 4] = [0x6745_2301, 0xefcd_ab89, 0x98ba_dcfe, 0x1032_5476];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64).wrapping_mul(8).to_le_bytes());
+    for block in message.chunks_exact(64) {
+        let words: [u32; 16] = std::array::from_fn(|i| {
+            u32::from_le_bytes(block[4 * i..4 * i + 4].try_into().unwrap())
+        });
+        let [mut a, mut b, mut c, mut d] = state;
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+            let rotated = a
+                .wrapping_add(f)
+                .wrapping_add(k[i])
+                .wrapping_add(words[g])
+                .rotate_left(SHIFTS[i / 16 * 4 + i % 4]);
+            (a, b, c, d) = (d, b.wrapping_add(rotated), b, c);
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+    let mut digest = [0; 16];
+    for (i, s) in state.iter().enumerate() {
+        digest[4 * i..4 * i + 4].copy_from_slice(&s.to_le_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::ffi::OsStr};
+
+    #[test]
+    fn md5_test_suite() {
+        // From RFC 1321, appendix A.5
+        let suite = [
+            ("", "d41d8cd98f00b204e9800998ecf8427e"),
+            ("a", "0cc175b9c0f1b6a831c399e269772661"),
+            ("abc", "900150983cd24fb0d6963f7d28e17f72"),
+            ("message digest", "f96b697d7cb7938d525a2f31aaf161d0"),
+            (
+                "abcdefghijklmnopqrstuvwxyz",
+                "c3fcd3d76192e4007dfb496cca67e13b",
+            ),
+            (
+                "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789",
+                "d174ab98d277d9f5a5611c2c9f419d9f",
+            ),
+            (
+                "12345678901234567890123456789012345678901234567890123456789012345678901234567890",
+                "57edf4a22be3c955ac49da2e2107b67a",
+            ),
+        ];
+        for (input, digest) in suite {
+            assert_eq!(hex(&md5(input.as_bytes())), digest, "MD5 of {input:?}");
+        }
+    }
+
+    #[test]
+    fn md5_padding_edges() {
+        // 55 bytes fit in one block with the length, 56 need another
+        assert_eq!(hex(&md5(&[b'a'; 55])), "ef1772b6dff9a122358552954ad0df65");
+        assert_eq!(hex(&md5(&[b'a'; 56])), "3b0c8ac703f828b04c6c197006d17218");
+        assert_eq!(hex(&md5(&[b'a'; 64])), "014842d480b571495a4a0363793f7367");
+    }
+
+    #[test]
+    fn name_from_the_spec() {
+        assert_eq!(
+            thumbnail_name("file:///home/jens/photos/me.png"),
+            "c6ee772d9e49320e97ec29a7eb5b1697.png"
+        );
+    }
+
+    #[test]
+    fn uri_escapes_like_glib() {
+        // What `g_filename_to_uri` returns for these paths
+        assert_eq!(
+            uri(Path::new("/home/me/My Photos/été (1)+[2]#3.png")),
+            "file:///home/me/My%20Photos/%C3%A9t%C3%A9%20(1)+%5B2%5D%233.png"
+        );
+        assert_eq!(
+            uri(Path::new("/tmp/a,b;c=d@e$f&g'h~i!j*k:l%m?n")),
+            "file:///tmp/a,b%3Bc=d@e$f&g'h~i!j*k:l%25m%3Fn"
+        );
+        // Bytes of names that aren't UTF-8 are escaped one by one
+        let latin1 = Path::new(OsStr::from_bytes(b"/tmp/caf\xe9.txt"));
+        assert_eq!(uri(latin1), "file:///tmp/caf%E9.txt");
+    }
+
+    #[test]
+    fn png_text_chunks() {
+        let chunk = |kind: &[u8], data: &[u8]| {
+            let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+            chunk.extend(kind);
+            chunk.extend(data);
+            // The CRC isn't checked
+            chunk.extend([0; 4]);
+            chunk
+        };
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend(chunk(b"IHDR", &[0; 13]));
+        png.extend(chunk(b"tEXt", b"Thumb::MTime\x001700000000"));
+        png.extend(chunk(b"IDAT", &[0; 8]));
+        png.extend(chunk(b"tEXt", b"Thumb::URI\x00file:///a.pdf"));
+        png.extend(chunk(b"IEND", &[]));
+        assert_eq!(
+            png_texts(&png).unwrap(),
+            [
+                ("Thumb::MTime", "1700000000"),
+                ("Thumb::URI", "file:///a.pdf")
+            ]
+        );
+        assert!(png_texts(b"GIF89a").is_none());
+        // Cut off in the middle of the first text
+        assert!(png_texts(&png[..50]).is_none());
+    }
+}
@@ -0,0 +1,61 @@
+//! Record of the steps taken while resolving an argument, for `--explain` and `--dry-run`
+
+pub struct Step {
+    pub kind: StepKind,
+    pub detail: String,
+    /// Where the information came from (a file, a helper command line, ...)
+    pub source: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum StepKind {
+    /// How the argument was classified (path, URL, ...)
+    Classify,
+    /// A helper program invocation
+    Helper,
+    /// A MIME type detection step
+    Mime,
+    /// A `mimeapps.list` file consulted for the default application
+    Association,
+    /// The default application that was chosen
+    Default,
+    /// The desktop entry of the chosen application
+    DesktopFile,
+}
+
+impl StepKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Classify => "classify",
+            Self::Helper => "helper",
+            Self::Mime => "mime",
+            Self::Association => "association",
+            Self::Default => "default",
+            Self::DesktopFile => "desktop-file",
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Trace {
+    pub steps: Vec<Step>,
+}
+
+impl Trace {
+    pub fn push(&mut self, kind: StepKind, detail: impl Into<String>, source: Option<String>) {
+        self.steps.push(Step {
+            kind,
+            detail: detail.into(),
+            source,
+        });
+    }
+
+    /// The detail of the last step of kind `kind`
+    pub fn last(&self, kind: StepKind) -> Option<&str> {
+        self.steps
+            .iter()
+            .rev()
+            .find(|step| step.kind == kind)
+            .map(|step| step.detail.as_str())
+    }
+}
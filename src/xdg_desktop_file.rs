@@ -1,9 +1,15 @@
-use std::{
-    collections::HashMap,
-    ffi::{OsStr, OsString},
-    path::Path,
+use {
+    crate::basedir,
+    std::{
+        collections::HashMap,
+        ffi::{OsStr, OsString},
+        path::{Path, PathBuf},
+    },
 };
 
+/// Exec field codes that stand for the file or URL being opened
+const FILE_CODES: [&str; 4] = ["%U", "%u", "%f", "%F"];
+
 pub fn args_from_exec_string(exec: &str, arg: &OsStr) -> Option<(String, Vec<OsString>)> {
     let mut tokens = shlex::split(exec)?;
     if tokens.is_empty() {
@@ -13,7 +19,7 @@ pub fn args_from_exec_string(exec: &str, arg: &OsStr) -> Option<(String, Vec<OsS
     let args = tokens
         .into_iter()
         .map(|tok| {
-            if tok == "%U" || tok == "%u" || tok == "%f" || tok == "%F" {
+            if FILE_CODES.contains(&tok.as_str()) {
                 arg.to_owned()
             } else {
                 tok.into()
@@ -23,7 +29,36 @@ pub fn args_from_exec_string(exec: &str, arg: &OsStr) -> Option<(String, Vec<OsS
     Some((exec, args))
 }
 
-type DesktopMap = HashMap<String, String>;
+/// Like [`args_from_exec_string`], but for a command line given by the user.
+///
+/// If it doesn't contain a field code like `%f`, `arg` is appended.
+pub fn args_from_command(cmd: &str, arg: &OsStr) -> Option<(String, Vec<OsString>)> {
+    let (exec, mut args) = args_from_exec_string(cmd, arg)?;
+    let has_field_code = shlex::split(cmd)?
+        .iter()
+        .any(|tok| FILE_CODES.contains(&tok.as_str()));
+    if !has_field_code {
+        args.push(arg.to_owned());
+    }
+    Some((exec, args))
+}
+
+/// Shell-quote `words` into a command line, leaving field codes like `%f` bare, since quoting
+/// them would look like they're meant literally
+pub fn quote_command<S: AsRef<str>>(words: &[S]) -> Option<String> {
+    let mut quoted = Vec::new();
+    for word in words {
+        let word = word.as_ref();
+        if FILE_CODES.contains(&word) {
+            quoted.push(word.to_owned());
+        } else {
+            quoted.push(shlex::try_quote(word).ok()?.into_owned());
+        }
+    }
+    Some(quoted.join(" "))
+}
+
+pub type DesktopMap = HashMap<String, String>;
 
 enum ParseStatus {
     // Initial status, trying to find desktop entry group
@@ -58,3 +93,101 @@ pub fn parse_desktop_file(path: impl AsRef<Path>) -> Result<DesktopMap, std::io:
     }
     Ok(map)
 }
+
+/// Find the desktop entry file for desktop file ID `id` (e.g. `org.kde.kate.desktop`)
+/// in the `applications` directories of the XDG data dirs.
+///
+/// A `-` in the ID may stand for a subdirectory, so `kde4-kate.desktop` also matches
+/// `kde4/kate.desktop`.
+pub fn find_desktop_file(id: &str) -> Option<PathBuf> {
+    let dashes = id.matches('-').count();
+    basedir::data_dirs().into_iter().find_map(|dir| {
+        let apps = dir.join("applications");
+        (0..=dashes)
+            .map(|n| apps.join(id.replacen('-', "/", n)))
+            .find(|path| path.is_file())
+    })
+}
+
+/// All installed desktop entries, as (desktop file ID, path) pairs.
+///
+/// If the same ID exists in multiple data dirs, the most important one wins.
+pub fn installed_desktop_files() -> Vec<(String, PathBuf)> {
+    let mut found: Vec<(String, PathBuf)> = Vec::new();
+    for dir in basedir::data_dirs() {
+        let apps = dir.join("applications");
+        let mut stack = vec![apps.clone()];
+        while let Some(dir) = stack.pop() {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    stack.push(path);
+                    continue;
+                }
+                if path.extension().is_none_or(|ext| ext != "desktop") {
+                    continue;
+                }
+                let Some(id) = path
+                    .strip_prefix(&apps)
+                    .ok()
+                    .and_then(|rel| rel.to_str())
+                    .map(|rel| rel.replace('/', "-"))
+                else {
+                    continue;
+                };
+                if !found.iter().any(|(known, _)| *known == id) {
+                    found.push((id, path));
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Value of `key` localized for the user's locale (e.g. `Name[de]`), or the plain value
+pub fn localized<'a>(map: &'a DesktopMap, key: &str) -> Option<&'a str> {
+    locale_variants()
+        .iter()
+        .find_map(|locale| map.get(&format!("{key}[{locale}]")))
+        .or_else(|| map.get(key))
+        .map(String::as_str)
+}
+
+/// Locale names to try for localized keys, most specific first.
+///
+/// For `sr_YU@Latn`, these are `sr_YU@Latn`, `sr_YU`, `sr@Latn` and `sr`.
+fn locale_variants() -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    // Drop the encoding, e.g. `.UTF-8`
+    let (locale, modifier) = match locale.split_once('@') {
+        Some((locale, modifier)) => (locale, Some(modifier)),
+        None => (locale.as_str(), None),
+    };
+    let locale = locale.split('.').next().unwrap_or_default();
+    if locale.is_empty() || locale == "C" || locale == "POSIX" {
+        return Vec::new();
+    }
+    let (lang, country) = match locale.split_once('_') {
+        Some((lang, country)) => (lang, Some(country)),
+        None => (locale, None),
+    };
+    let mut variants = Vec::new();
+    if let (Some(country), Some(modifier)) = (country, modifier) {
+        variants.push(format!("{lang}_{country}@{modifier}"));
+    }
+    if let Some(country) = country {
+        variants.push(format!("{lang}_{country}"));
+    }
+    if let Some(modifier) = modifier {
+        variants.push(format!("{lang}@{modifier}"));
+    }
+    variants.push(lang.to_string());
+    variants
+}
@@ -0,0 +1,163 @@
+//! The `rusty-open mime` subcommands, compatible with `xdg-mime` from xdg-utils
+
+use {
+    crate::{
+        basedir,
+        cli::{self, InstallMode, MimeCommand},
+        mimeapps, shared_mime,
+    },
+    std::{
+        ffi::OsStr,
+        io::ErrorKind,
+        path::{Path, PathBuf},
+        process::{Command, Stdio},
+    },
+};
+
+/// Carry out `command`, returning the exit code
+pub fn run(command: MimeCommand) -> i32 {
+    match command {
+        MimeCommand::QueryFiletype(path) => query_filetype(Path::new(&path)),
+        MimeCommand::QueryDefault(mime) => {
+            // Like `xdg-mime`, having no default isn't an error
+            if let Some(id) = mimeapps::effective_default(&mime) {
+                println!("{id}");
+            }
+            0
+        }
+        MimeCommand::Default { desktop_id, mimes } => {
+            if !desktop_id.ends_with(".desktop") {
+                eprintln!("rusty-open: invalid application name '{desktop_id}'");
+                return cli::EXIT_SYNTAX;
+            }
+            match mimeapps::set_default(&desktop_id, &mimes) {
+                Ok(_) => 0,
+                Err(err) => fail(&err, "Couldn't update mimeapps.list"),
+            }
+        }
+        MimeCommand::Install {
+            mode,
+            novendor,
+            file,
+        } => install(mode, novendor, Path::new(&file)),
+        MimeCommand::Uninstall { mode, file } => uninstall(mode, Path::new(&file)),
+    }
+}
+
+fn query_filetype(path: &Path) -> i32 {
+    if let Err(err) = path.symlink_metadata() {
+        return file_error(path, &err);
+    }
+    match shared_mime::detect(path) {
+        Some((mime, _)) => {
+            println!("{mime}");
+            0
+        }
+        None => {
+            eprintln!("rusty-open: no shared MIME database found");
+            cli::EXIT_TOOL_MISSING
+        }
+    }
+}
+
+fn install(mode: InstallMode, novendor: bool, file: &Path) -> i32 {
+    if let Err(err) = std::fs::File::open(file) {
+        return file_error(file, &err);
+    }
+    let Some(name) = file.file_name() else {
+        return file_error(file, &ErrorKind::NotFound.into());
+    };
+    if !novendor && !has_vendor_prefix(name) {
+        eprintln!(
+            "rusty-open: filename '{}' does not have a proper vendor prefix\n\
+             A vendor prefix consists of alpha characters ([a-zA-Z]) and is terminated\n\
+             with a dash (\"-\"). Use --novendor to override.",
+            name.to_string_lossy()
+        );
+        return cli::EXIT_SYNTAX;
+    }
+    let Some(mime_dir) = mime_dir(mode) else {
+        eprintln!("rusty-open: couldn't determine where to install to");
+        return cli::EXIT_FAILED;
+    };
+    let packages = mime_dir.join("packages");
+    if let Err(err) = std::fs::create_dir_all(&packages)
+        .and_then(|()| std::fs::copy(file, packages.join(name)).map(drop))
+    {
+        return fail(&err, "Couldn't install");
+    }
+    update_database(&mime_dir)
+}
+
+fn uninstall(mode: InstallMode, file: &Path) -> i32 {
+    let (Some(name), Some(mime_dir)) = (file.file_name(), mime_dir(mode)) else {
+        eprintln!("rusty-open: couldn't determine what to uninstall");
+        return cli::EXIT_FAILED;
+    };
+    match std::fs::remove_file(mime_dir.join("packages").join(name)) {
+        // Nothing to remove is fine, like with `xdg-mime`
+        Err(err) if err.kind() != ErrorKind::NotFound => fail(&err, "Couldn't uninstall"),
+        _ => update_database(&mime_dir),
+    }
+}
+
+/// The `mime` directory to install to, according to `mode`
+fn mime_dir(mode: InstallMode) -> Option<PathBuf> {
+    let dir = match mode {
+        InstallMode::User => dirs::data_dir(),
+        InstallMode::System => basedir::system_data_dirs().into_iter().next(),
+    };
+    dir.map(|dir| dir.join("mime"))
+}
+
+/// Whether a file name starts with letters followed by a dash, like `vendor-foo.xml`
+fn has_vendor_prefix(name: &OsStr) -> bool {
+    name.to_str()
+        .and_then(|name| name.split_once('-'))
+        .is_some_and(|(vendor, _)| {
+            !vendor.is_empty() && vendor.bytes().all(|b| b.is_ascii_alphabetic())
+        })
+}
+
+/// Rebuild the database in `mime_dir` after its packages changed
+fn update_database(mime_dir: &Path) -> i32 {
+    let status = Command::new("update-mime-database")
+        .arg(mime_dir)
+        .stdout(Stdio::null())
+        .status();
+    match status {
+        Ok(status) if status.success() => 0,
+        Ok(status) => {
+            eprintln!("rusty-open: update-mime-database exited with {status}");
+            cli::EXIT_FAILED
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            eprintln!("rusty-open: update-mime-database is not installed");
+            cli::EXIT_TOOL_MISSING
+        }
+        Err(err) => fail(&err, "Couldn't run update-mime-database"),
+    }
+}
+
+/// Report a problem with a file given on the command line
+fn file_error(path: &Path, err: &std::io::Error) -> i32 {
+    match err.kind() {
+        ErrorKind::NotFound => {
+            eprintln!("rusty-open: file '{}' does not exist", path.display());
+            cli::EXIT_NOT_FOUND
+        }
+        ErrorKind::PermissionDenied => {
+            eprintln!(
+                "rusty-open: no permission to read file '{}'",
+                path.display()
+            );
+            cli::EXIT_NO_PERMISSION
+        }
+        _ => fail(err, &path.display().to_string()),
+    }
+}
+
+fn fail(err: &std::io::Error, context: &str) -> i32 {
+    eprintln!("rusty-open: {context}: {err}");
+    cli::EXIT_FAILED
+}